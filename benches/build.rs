@@ -0,0 +1,247 @@
+//! Measures how long it takes to build graphs from metadata, both for the test
+//! fixtures, and for a large synthetic graph, run via `cargo bench`
+
+use std::time::{Duration, Instant};
+
+fn load(fixture: &str) -> krates::cm::Metadata {
+    let contents = std::fs::read_to_string(format!("tests/{fixture}")).unwrap();
+    serde_json::from_str(&contents).unwrap()
+}
+
+/// A simple LCG so that the synthetic graph is the same for every run
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) as usize
+    }
+
+    fn one_in(&mut self, n: usize) -> bool {
+        self.next() % n == 0
+    }
+}
+
+/// Generates metadata for a workspace with `count` crates, as if it had been
+/// retrieved with `--all-features`
+fn synthetic(count: usize) -> krates::cm::Metadata {
+    use serde_json::json;
+
+    const WORKSPACE: usize = 20;
+
+    let mut rng = Rng(0x5eed);
+
+    let name = |i: usize| {
+        if i < WORKSPACE {
+            format!("ws{i}")
+        } else {
+            format!("k{i}")
+        }
+    };
+    let id = |i: usize| {
+        if i < WORKSPACE {
+            format!("ws{i} 0.1.0 (path+file:///bench/ws{i})")
+        } else {
+            format!("k{i} 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)")
+        }
+    };
+
+    // Ensure every crate is reachable from the workspace, then add more edges
+    // to make it a bit more realistic
+    let mut deps = vec![std::collections::BTreeSet::new(); count];
+    for (i, deps) in deps.iter_mut().enumerate().take(WORKSPACE) {
+        deps.insert(WORKSPACE + i);
+    }
+    for dep in WORKSPACE..count {
+        let parent = WORKSPACE + rng.next() % (dep - WORKSPACE).max(1);
+        if parent < dep {
+            deps[parent].insert(dep);
+        }
+    }
+    for (krate, deps) in deps.iter_mut().enumerate() {
+        let start = krate.max(WORKSPACE) + 1;
+        if start >= count {
+            continue;
+        }
+
+        for _ in 0..rng.next() % 6 {
+            deps.insert(start + rng.next() % (count - start).min(300));
+        }
+    }
+
+    let mut packages = Vec::with_capacity(count);
+    let mut nodes = Vec::with_capacity(count);
+
+    for (krate, deps) in deps.iter().enumerate() {
+        let mut declared = Vec::new();
+        let mut resolved = Vec::new();
+        let mut optional = Vec::new();
+
+        for &dep in deps {
+            let kind = if krate < WORKSPACE && rng.one_in(8) {
+                Some("dev")
+            } else if rng.one_in(10) {
+                Some("build")
+            } else {
+                None
+            };
+            let target = match rng.next() % 10 {
+                0 => Some("cfg(unix)"),
+                1 => Some("cfg(windows)"),
+                _ => None,
+            };
+            let is_optional = kind.is_none() && rng.one_in(5);
+            if is_optional {
+                optional.push(dep);
+            }
+
+            let features: &[&str] = if rng.one_in(3) { &["f1"] } else { &[] };
+
+            declared.push(json!({
+                "name": name(dep),
+                "source": (dep >= WORKSPACE).then_some("registry+https://github.com/rust-lang/crates.io-index"),
+                "req": if dep < WORKSPACE { "^0.1.0" } else { "^1.0" },
+                "kind": kind,
+                "rename": null,
+                "optional": is_optional,
+                "uses_default_features": !rng.one_in(6),
+                "features": features,
+                "target": target,
+                "registry": null,
+                "path": (dep < WORKSPACE).then(|| format!("/bench/ws{dep}")),
+            }));
+
+            resolved.push(json!({
+                "name": name(dep),
+                "pkg": id(dep),
+                "dep_kinds": [{ "kind": kind, "target": target }],
+            }));
+        }
+
+        let mut f2 = Vec::new();
+        let mut f3 = Vec::new();
+        for dep in &optional {
+            f2.push(format!("dep:{}", name(*dep)));
+            f2.push(format!("{}/f1", name(*dep)));
+            f3.push(format!("{}?/f2", name(*dep)));
+        }
+
+        let (version, source, manifest_path) = if krate < WORKSPACE {
+            (
+                "0.1.0".to_owned(),
+                None,
+                format!("/bench/ws{krate}/Cargo.toml"),
+            )
+        } else {
+            (
+                "1.0.0".to_owned(),
+                Some("registry+https://github.com/rust-lang/crates.io-index"),
+                format!("/registry/k{krate}-1.0.0/Cargo.toml"),
+            )
+        };
+
+        packages.push(json!({
+            "name": name(krate),
+            "version": version,
+            "id": id(krate),
+            "source": source,
+            "dependencies": declared,
+            "targets": [{
+                "kind": ["lib"],
+                "crate_types": ["lib"],
+                "name": name(krate),
+                "src_path": format!("/src/{}/lib.rs", name(krate)),
+                "edition": "2021",
+            }],
+            "features": {
+                "default": ["f0"],
+                "f0": [],
+                "f1": ["f0"],
+                "f2": f2,
+                "f3": f3,
+            },
+            "manifest_path": manifest_path,
+        }));
+
+        nodes.push(json!({
+            "id": id(krate),
+            "dependencies": deps.iter().map(|d| id(*d)).collect::<Vec<_>>(),
+            "deps": resolved,
+            "features": ["default", "f0", "f1", "f2", "f3"],
+        }));
+    }
+
+    let workspace_members: Vec<_> = (0..WORKSPACE).map(id).collect();
+
+    serde_json::from_value(json!({
+        "packages": packages,
+        "workspace_members": workspace_members,
+        "workspace_default_members": workspace_members,
+        "resolve": {
+            "nodes": nodes,
+            "root": null,
+        },
+        "target_directory": "/bench/target",
+        "version": 1,
+        "workspace_root": "/bench",
+        "metadata": null,
+    }))
+    .unwrap()
+}
+
+fn bench(name: &str, md: &krates::cm::Metadata, configure: impl Fn(&mut krates::Builder)) {
+    const ITERATIONS: usize = 10;
+
+    let mut timings = Vec::with_capacity(ITERATIONS);
+    let mut nodes = 0;
+
+    for _ in 0..ITERATIONS {
+        let md = md.clone();
+        let mut kb = krates::Builder::new();
+        configure(&mut kb);
+
+        let start = Instant::now();
+        let krates: krates::Krates = kb.build_with_metadata(md, krates::NoneFilter).unwrap();
+        timings.push(start.elapsed());
+
+        nodes = krates.graph().node_count();
+    }
+
+    timings.sort();
+    let mean = timings.iter().sum::<Duration>() / ITERATIONS as u32;
+
+    println!(
+        "{name:<40} {nodes:>7} nodes  median {:>10.3?}  mean {:>10.3?}",
+        timings[ITERATIONS / 2],
+        mean
+    );
+}
+
+fn main() {
+    for fixture in [
+        "all-features.json",
+        "all-features2.json",
+        "direct.json",
+        "pid-opaque.json",
+    ] {
+        let md = load(fixture);
+        bench(fixture, &md, |_kb| {});
+        bench(&format!("{fixture} (linux)"), &md, |kb| {
+            kb.include_targets([("x86_64-unknown-linux-gnu", vec![])]);
+        });
+    }
+
+    for count in [1000, 3500] {
+        let md = synthetic(count);
+        bench(&format!("synthetic {count}"), &md, |_kb| {});
+        bench(&format!("synthetic {count} (linux)"), &md, |kb| {
+            kb.include_targets([("x86_64-unknown-linux-gnu", vec![])]);
+        });
+        bench(&format!("synthetic {count} (crates only)"), &md, |kb| {
+            kb.crates_only(true);
+        });
+    }
+}
@@ -67,12 +67,83 @@ impl Grafs {
     }
 }
 
-pub fn build<P: AsRef<Path>>(src: P, kb: krates::Builder) -> Result<Grafs, String> {
+/// Loads the metadata from a file in the tests directory
+pub fn metadata<P: AsRef<Path>>(src: P) -> Result<krates::cm::Metadata, String> {
     let contents = std::fs::read_to_string(Path::new("tests").join(src))
         .map_err(|e| format!("failed to load metadata file: {e}"))?;
 
-    let md: krates::cm::Metadata = serde_json::from_str(&contents)
-        .map_err(|e| format!("failed to deserialize metadata: {e}"))?;
+    serde_json::from_str(&contents).map_err(|e| format!("failed to deserialize metadata: {e}"))
+}
+
+/// Builds a graph from a metadata file in the tests directory, for the tests
+/// that need more than just the id of each crate
+pub fn build_krates<N, P>(src: P, kb: krates::Builder) -> Result<krates::Krates<N>, String>
+where
+    N: From<krates::cm::Package>,
+    P: AsRef<Path>,
+{
+    let md = metadata(src)?;
+    kb.build_with_metadata(md, krates::NoneFilter)
+        .map_err(|e| format!("failed to build graph: {e}"))
+}
+
+/// Builds a graph with a dev-dependency cycle, memchr <- aho-corasick <-(dev) memchr
+pub fn dev_cycle_krates() -> (krates::Krates, krates::NodeId, krates::NodeId) {
+    let contents = std::fs::read_to_string(Path::new("tests").join("all-features.json")).unwrap();
+    let mut md: serde_json::Value = serde_json::from_str(&contents).unwrap();
+
+    let memchr_id = "memchr 2.2.1 (registry+https://github.com/rust-lang/crates.io-index)";
+    let aho_id = "aho-corasick 0.7.6 (registry+https://github.com/rust-lang/crates.io-index)";
+
+    for pkg in md["packages"].as_array_mut().unwrap() {
+        if pkg["id"] == memchr_id {
+            pkg["dependencies"]
+                .as_array_mut()
+                .unwrap()
+                .push(serde_json::json!({
+                    "name": "aho-corasick",
+                    "source": "registry+https://github.com/rust-lang/crates.io-index",
+                    "req": "^0.7",
+                    "kind": "dev",
+                    "rename": null,
+                    "optional": false,
+                    "uses_default_features": true,
+                    "features": [],
+                    "target": null,
+                    "registry": null,
+                }));
+        }
+    }
+
+    for node in md["resolve"]["nodes"].as_array_mut().unwrap() {
+        if node["id"] == memchr_id {
+            node["deps"]
+                .as_array_mut()
+                .unwrap()
+                .push(serde_json::json!({
+                    "name": "aho_corasick",
+                    "pkg": aho_id,
+                    "dep_kinds": [{ "kind": "dev", "target": null }],
+                }));
+        }
+    }
+
+    let md: krates::cm::Metadata = serde_json::from_value(md).unwrap();
+    let krates: krates::Krates = krates::Builder::new()
+        .build_with_metadata(md, krates::NoneFilter)
+        .unwrap();
+
+    let memchr = krates.krates_by_name("memchr").next().unwrap().node_id;
+    let aho = krates
+        .krates_by_name("aho-corasick")
+        .next()
+        .unwrap()
+        .node_id;
+    (krates, memchr, aho)
+}
+
+pub fn build<P: AsRef<Path>>(src: P, kb: krates::Builder) -> Result<Grafs, String> {
+    let md = metadata(src)?;
 
     let resolved = md.resolve.as_ref().cloned().unwrap();
 
@@ -96,7 +167,7 @@ pub fn build<P: AsRef<Path>>(src: P, kb: krates::Builder) -> Result<Grafs, Strin
                             d.dep_kinds.into_iter().map(move |dk| {
                                 (
                                     id.clone().into(),
-                                    krates::Edge::Dep {
+                                    SimpleEdge {
                                         kind: dk.kind.into(),
                                         cfg: dk.target.clone(),
                                     },
@@ -130,8 +201,32 @@ pub fn is_workspace(kid: &krates::Kid) -> bool {
     kid.repr.starts_with("a ") || kid.repr.starts_with("b ") | kid.repr.starts_with("c ")
 }
 
+/// A dependency edge straight from the resolve graph, without the declaration
+/// that `krates` matches it to
+#[derive(Clone)]
+pub struct SimpleEdge {
+    pub kind: krates::DepKind,
+    pub cfg: Option<String>,
+}
+
+impl fmt::Display for SimpleEdge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            krates::DepKind::Normal => {}
+            krates::DepKind::Build => f.write_str("(build)")?,
+            krates::DepKind::Dev => f.write_str("(dev)")?,
+        };
+
+        if let Some(cfg) = &self.cfg {
+            write!(f, " '{cfg}'")?;
+        }
+
+        Ok(())
+    }
+}
+
 pub struct SimpleGraph {
-    pub nodes: Vec<(krates::Kid, Vec<(krates::Kid, krates::Edge)>)>,
+    pub nodes: Vec<(krates::Kid, Vec<(krates::Kid, SimpleEdge)>)>,
     workspace: Vec<krates::Kid>,
 }
 
@@ -140,7 +235,7 @@ impl SimpleGraph {
         mut self,
         nf: NF,
         ef: EF,
-    ) -> krates::petgraph::Graph<JustId, krates::Edge> {
+    ) -> krates::petgraph::Graph<JustId, SimpleEdge> {
         self.nodes.sort_by(|a, b| a.0.cmp(&b.0));
 
         let mut graph = krates::petgraph::Graph::new();
@@ -166,14 +261,10 @@ impl SimpleGraph {
                     if ef(EdgeFilter {
                         source: kid,
                         target: pid,
-                        dep: if let krates::Edge::Dep { kind, cfg } = edge {
-                            Some(EdgeDepFilter {
-                                kind: *kind,
-                                cfg: cfg.as_deref(),
-                            })
-                        } else {
-                            None
-                        },
+                        dep: Some(EdgeDepFilter {
+                            kind: edge.kind,
+                            cfg: edge.cfg.as_deref(),
+                        }),
                     }) {
                         None
                     } else {
@@ -1,12 +1,16 @@
 pub(crate) mod features;
 
 pub mod index;
+mod target;
 
-use crate::{DepKind, Edge, Error, Kid, Krates, MdTarget, cm};
+pub use target::CustomTarget;
+
+use crate::{DepKind, Diagnostic, Edge, Error, Kid, Krates, MdTarget, cm};
 use features::{Feature, ParsedFeature};
 use std::{
     collections::{BTreeMap, BTreeSet},
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 /// An alternative to [`cargo_metadata::MetadataCommand`] which allows correct
@@ -192,6 +196,8 @@ pub enum Target {
     Builtin(&'static cfg_expr::targets::TargetInfo),
     #[cfg(feature = "targets")]
     Triple(cfg_expr::target_lexicon::Triple),
+    /// A target created via [`Target::from_rustc_cfg`] or [`Target::from_target_spec`]
+    Custom(Box<CustomTarget>),
     Unknown(String),
 }
 
@@ -227,14 +233,76 @@ impl fmt::Display for Target {
             Self::Builtin(bi) => f.write_str(bi.triple.as_str()),
             #[cfg(feature = "targets")]
             Self::Triple(trip) => write!(f, "{}", trip),
+            Self::Custom(custom) => f.write_str(custom.info.triple.as_str()),
             Self::Unknown(unknown) => f.write_str(unknown),
         }
     }
 }
 
+/// A custom configuration option, the same as is specified via `--cfg` to rustc,
+/// eg. `RUSTFLAGS="--cfg tokio_unstable"`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cfg {
+    /// A name only option, eg. `tokio_unstable`, which matches `cfg(tokio_unstable)`
+    Flag(String),
+    /// A key and value option, eg. `docsrs="yes"`, which matches `cfg(docsrs = "yes")`
+    KeyValue { key: String, value: String },
+}
+
+impl std::str::FromStr for Cfg {
+    type Err = Error;
+
+    /// Parses a cfg in the same format as rustc's `--cfg` option, either
+    /// `name` or `key="value"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let is_ident = |s: &str| {
+            !s.is_empty()
+                && !s.starts_with(|c: char| c.is_ascii_digit())
+                && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+        };
+
+        let Some((key, value)) = s.split_once('=') else {
+            let flag = s.trim();
+            if !is_ident(flag) {
+                return Err(Error::InvalidCfg("the cfg name is not a valid identifier"));
+            }
+
+            return Ok(Self::Flag(flag.to_owned()));
+        };
+
+        let key = key.trim();
+        if !is_ident(key) {
+            return Err(Error::InvalidCfg("the cfg key is not a valid identifier"));
+        }
+
+        let Some(value) = value
+            .trim()
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+        else {
+            return Err(Error::InvalidCfg("the cfg value is not a quoted string"));
+        };
+
+        Ok(Self::KeyValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for Cfg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flag(flag) => f.write_str(flag),
+            Self::KeyValue { key, value } => write!(f, "{key}=\"{value}\""),
+        }
+    }
+}
+
 struct TargetFilter {
     inner: Target,
     features: Vec<String>,
+    cfgs: Vec<Cfg>,
 }
 
 impl TargetFilter {
@@ -244,21 +312,52 @@ impl TargetFilter {
                 Target::Builtin(bi) => tp.matches(*bi),
                 #[cfg(feature = "targets")]
                 Target::Triple(trip) => tp.matches(trip),
+                Target::Custom(custom) => tp.matches(&custom.info),
                 Target::Unknown(_) => false,
             },
             cfg_expr::expr::Predicate::TargetFeature(feat) => {
-                // TODO: target_features are extremely rare in cargo.toml
-                // files, it might be a good idea to inform the user of this
-                // somehow, if they are unsure why a particular dependency
-                // is being filtered
-                self.features.iter().any(|f| f == feat)
+                // target_features are extremely rare in cargo.toml files, so
+                // the user is informed via a Diagnostic if one decides
+                // whether a dependency is included
+                let custom = match &self.inner {
+                    Target::Custom(custom) => custom.features.as_slice(),
+                    _ => &[],
+                };
+
+                self.features.iter().chain(custom).any(|f| f == feat)
+                    || self.has_key_value("target_feature", feat)
             }
-            // We *could* warn here about an invalid expression, but
-            // presumably cargo will be responsible for that, so don't bother
-            _ => false,
+            cfg_expr::expr::Predicate::Test => self.has_flag("test"),
+            cfg_expr::expr::Predicate::DebugAssertions => self.has_flag("debug_assertions"),
+            cfg_expr::expr::Predicate::ProcMacro => self.has_flag("proc_macro"),
+            cfg_expr::expr::Predicate::Flag(flag) => self.has_flag(flag),
+            cfg_expr::expr::Predicate::Feature(feat) => self.has_key_value("feature", feat),
+            cfg_expr::expr::Predicate::KeyValue { key, val } => self.has_key_value(key, val),
         }
     }
 
+    #[inline]
+    fn cfgs(&self) -> impl Iterator<Item = &Cfg> {
+        let custom = match &self.inner {
+            Target::Custom(custom) => custom.cfgs.as_slice(),
+            _ => &[],
+        };
+
+        self.cfgs.iter().chain(custom)
+    }
+
+    #[inline]
+    fn has_flag(&self, flag: &str) -> bool {
+        self.cfgs()
+            .any(|cfg| matches!(cfg, Cfg::Flag(f) if f == flag))
+    }
+
+    #[inline]
+    fn has_key_value(&self, key: &str, value: &str) -> bool {
+        self.cfgs()
+            .any(|cfg| matches!(cfg, Cfg::KeyValue { key: k, value: v } if k == key && v == value))
+    }
+
     fn matches_triple(&self, triple: &str) -> bool {
         match &self.inner {
             Target::Builtin(bi) => bi.triple.as_str() == triple,
@@ -267,6 +366,7 @@ impl TargetFilter {
                 let as_triple = format!("{}", trip);
                 as_triple == triple
             }
+            Target::Custom(custom) => custom.info.triple.as_str() == triple,
             Target::Unknown(unknown) => unknown == triple,
         }
     }
@@ -305,16 +405,107 @@ where
     }
 }
 
+/// The context available when creating a crate node via a [`NodeFactory`]
+pub struct NodeContext<'ctx> {
+    /// The unique identifier for the crate
+    pub id: &'ctx Kid,
+    /// The features enabled on the crate
+    pub features: &'ctx crate::EnabledFeatures,
+    /// The features enabled on the crate for each kind of compile unit, only
+    /// present if [`Builder::unit_features`] was enabled
+    pub unit_features: Option<&'ctx crate::UnitFeatures>,
+    /// True if the crate is a member of the workspace
+    pub is_workspace_member: bool,
+    /// The minimum number of dependency edges between the crate and any of
+    /// the roots of the graph
+    pub depth: usize,
+}
+
+/// Trait used to create the crate nodes in the graph, with more context than
+/// is available with just `From<Package>`
+pub trait NodeFactory<N> {
+    /// Creates the node for a crate that is part of the graph, called once
+    /// for each crate, in the same order as the crates appear in the graph
+    fn create_node(&mut self, ctx: NodeContext<'_>, krate: cm::Package) -> N;
+}
+
+impl<N, F> NodeFactory<N> for F
+where
+    F: FnMut(NodeContext<'_>, cm::Package) -> N,
+{
+    fn create_node(&mut self, ctx: NodeContext<'_>, krate: cm::Package) -> N {
+        self(ctx, krate)
+    }
+}
+
+/// The context available when creating an edge via an [`EdgeFactory`]
+pub struct EdgeContext<'ctx> {
+    /// The package the edge starts from
+    pub source: &'ctx cm::Package,
+    /// The feature the edge starts from, if the source is a feature node
+    pub source_feature: Option<&'ctx str>,
+    /// The package the edge points to
+    pub target: &'ctx cm::Package,
+    /// The feature the edge points to, if the target is a feature node
+    pub target_feature: Option<&'ctx str>,
+}
+
+/// Trait used to create the edges in the graph, with more context than is
+/// available with just `From<Edge>`
+pub trait EdgeFactory<E> {
+    /// Creates an edge in the graph, called once for each edge as it is
+    /// linked, before any of the crate nodes are created
+    fn create_edge(&mut self, ctx: EdgeContext<'_>, edge: Edge) -> E;
+}
+
+impl<E, F> EdgeFactory<E> for F
+where
+    F: FnMut(EdgeContext<'_>, Edge) -> E,
+{
+    fn create_edge(&mut self, ctx: EdgeContext<'_>, edge: Edge) -> E {
+        self(ctx, edge)
+    }
+}
+
+/// The factory used when the graph is built without any factories, which just
+/// creates each node and edge via [`From`]
+pub struct DefaultFactory;
+
+impl<N> NodeFactory<N> for DefaultFactory
+where
+    N: From<cm::Package>,
+{
+    fn create_node(&mut self, _ctx: NodeContext<'_>, krate: cm::Package) -> N {
+        N::from(krate)
+    }
+}
+
+impl<E> EdgeFactory<E> for DefaultFactory
+where
+    E: From<Edge>,
+{
+    fn create_edge(&mut self, _ctx: EdgeContext<'_>, edge: Edge) -> E {
+        E::from(edge)
+    }
+}
+
 /// A builder used to create a Krates graph, either by running a cargo metadata
 /// command, or using an already deserialized [`cargo_metadata::Metadata`]
 #[derive(Default)]
 pub struct Builder {
     target_filters: Vec<TargetFilter>,
     workspace_filters: Vec<PathBuf>,
+    workspace_specs: Vec<crate::PkgSpec>,
     exclude: Vec<crate::PkgSpec>,
     ignore_kinds: u32,
     workspace: bool,
+    default_members: bool,
+    unit_features: bool,
+    root_features: BTreeMap<Kid, (Vec<String>, bool)>,
+    crates_only: bool,
+    max_depth: Option<usize>,
     crates_io_index: Option<index::BuildIndexCache>,
+    on_diagnostic: Option<Box<dyn FnMut(Diagnostic) + Send>>,
 }
 
 impl Builder {
@@ -380,6 +571,121 @@ impl Builder {
         self
     }
 
+    /// By default, the roots of the graph are either the crate that `cargo
+    /// metadata` was run for, or every workspace member.
+    ///
+    /// Setting this to true will instead use the workspace's [`default-members`](https://doc.rust-lang.org/cargo/reference/workspaces.html#the-default-members-field)
+    /// as the roots, the same crates a plain `cargo build` in the root of the
+    /// workspace would build. If the metadata was retrieved with a cargo
+    /// version older than 1.71, which doesn't output the default members,
+    /// every workspace member is used instead.
+    ///
+    /// This is ignored if [`Builder::include_workspace_crates`] or
+    /// [`Builder::include_workspace_specs`] are used.
+    ///
+    /// ```
+    /// # use krates::Builder;
+    /// Builder::new().default_members(true);
+    /// ```
+    pub fn default_members(&mut self, default_members: bool) -> &mut Self {
+        self.default_members = default_members;
+        self
+    }
+
+    /// By default, the features enabled on each crate are unified across every
+    /// path to that crate, the same as they are reported by `cargo metadata`.
+    ///
+    /// Setting this to true will additionally compute the features that are
+    /// enabled on each crate for each kind of compile unit it is built as, the
+    /// same as cargo's [version 2 feature resolver](https://doc.rust-lang.org/cargo/reference/resolver.html#feature-resolver-version-2).
+    /// Features enabled on build dependencies and proc-macros, as well as their
+    /// dependencies, are not unified with features enabled on normal
+    /// dependencies, and features enabled by dev-dependencies are only used
+    /// when building the workspace crates' tests.
+    ///
+    /// The features for each unit are available via [`Krates::get_unit_features`].
+    ///
+    /// ```
+    /// # use krates::Builder;
+    /// Builder::new().unit_features(true);
+    /// ```
+    pub fn unit_features(&mut self, unit_features: bool) -> &mut Self {
+        self.unit_features = unit_features;
+        self
+    }
+
+    /// By default, the graph contains a [`crate::Node::Feature`] for each
+    /// feature enabled on each crate, linked to crates and other features via
+    /// [`Edge::Feature`] and [`Edge::DepFeature`] edges.
+    ///
+    /// Setting this to true will instead create a graph of only
+    /// [`crate::Node::Krate`] nodes, linked by one [`Edge::Dep`] for each unique
+    /// kind and cfg that a crate depends on another crate with. The features
+    /// enabled on each crate are still resolved the same, which is useful for
+    /// tools that only care about crates, eg. license scanners, as the graph
+    /// is smaller and faster to build.
+    ///
+    /// ```
+    /// # use krates::Builder;
+    /// Builder::new().crates_only(true);
+    /// ```
+    pub fn crates_only(&mut self, crates_only: bool) -> &mut Self {
+        self.crates_only = crates_only;
+        self
+    }
+
+    /// By default, every crate reachable from the roots of the graph is
+    /// included.
+    ///
+    /// Setting a maximum depth will only include crates whose minimum distance
+    /// from any of the roots is at most the specified number of dependency
+    /// edges, ie. 0 is only the roots, 1 is the roots and their direct
+    /// dependencies. Crates that are further away are reported via
+    /// [`OnFilter`]. Note that the features enabled on the included crates
+    /// are still the ones enabled by the full graph.
+    ///
+    /// ```
+    /// # use krates::Builder;
+    /// Builder::new().max_depth(2);
+    /// ```
+    pub fn max_depth(&mut self, max_depth: usize) -> &mut Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// By default, the features enabled on each root crate are the ones that
+    /// were specified when `cargo metadata` was run, eg. via `--features` or
+    /// `--all-features`.
+    ///
+    /// This overrides the features enabled for the specified root crate, the
+    /// same as running cargo with `--no-default-features --features <features>`,
+    /// or `--features <features>` if `default` is true. The features of every
+    /// crate in the graph are then resolved from the new selection, allowing
+    /// many different feature combinations to be checked using the output of
+    /// a single `cargo metadata` invocation.
+    ///
+    /// Since features can only be enabled if cargo resolved them, the metadata
+    /// should be gathered with `--all-features`. Only features declared in the
+    /// root crate's manifest can be specified, and the crate must be one of the
+    /// roots of the graph, otherwise building the graph will fail.
+    ///
+    /// ```
+    /// # use krates::{Builder, Kid};
+    /// # let kid = Kid::default();
+    /// Builder::new().root_features(kid, ["serde"], false);
+    /// ```
+    pub fn root_features<I, S>(&mut self, kid: Kid, features: I, default: bool) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.root_features.insert(
+            kid,
+            (features.into_iter().map(Into::into).collect(), default),
+        );
+        self
+    }
+
     /// Package specification(s) to exclude from the final graph. Unlike with
     /// cargo, each exclusion spec can apply to more than 1 instance of a
     /// package, eg if multiple crates are sourced from the same url, or
@@ -442,6 +748,24 @@ impl Builder {
         self
     }
 
+    /// The same as [`Builder::include_workspace_crates`], except the workspace
+    /// crates used as root nodes are the ones matched by one of the specified
+    /// package specs rather than by their Cargo.toml path. Both methods can be
+    /// used together, in which case a workspace crate matched by either is
+    /// used as a root.
+    ///
+    /// ```
+    /// # use krates::Builder;
+    /// Builder::new().include_workspace_specs(["a-crate", "b-crate@0.1.0"].iter().map(|spec| spec.parse().unwrap()));
+    /// ```
+    pub fn include_workspace_specs<I>(&mut self, specs: I) -> &mut Self
+    where
+        I: IntoIterator<Item = crate::PkgSpec>,
+    {
+        self.workspace_specs.extend(specs);
+        self
+    }
+
     /// By default, cargo resolves all target specific dependencies. Optionally,
     /// you can use the `--filter-platform` option on `cargo metadata` to
     /// resolve only dependencies that match the specified target, but it can
@@ -456,7 +780,8 @@ impl Builder {
     ///
     /// When specifying a target triple, only builtin targets of rustc can be
     /// used to evaluate `cfg()` expressions. If the triple is not recognized,
-    /// it will only be evaluated against `[target.<triple-or-json>.<|build-|dev->dependencies]`.
+    /// it will only be evaluated against `[target.<triple-or-json>.<|build-|dev->dependencies]`,
+    /// unless it was created via [`Target::from_rustc_cfg`] or [`Target::from_target_spec`].
     ///
     /// ```
     /// # use krates::{Builder, DepKind, Scope};
@@ -487,10 +812,69 @@ impl Builder {
             .extend(targets.into_iter().map(|(triple, features)| TargetFilter {
                 inner: triple.into(),
                 features,
+                cfgs: Vec::new(),
             }));
         self
     }
 
+    /// The same as [`Self::include_targets`], but additionally allows custom
+    /// [`Cfg`] options to be specified for each target, the same as if they
+    /// were passed via `--cfg` in `RUSTFLAGS`.
+    ///
+    /// By default, `cfg()` expressions that use anything other than the
+    /// builtin target predicates, eg. `cfg(tokio_unstable)`, `cfg(docsrs)`, or
+    /// `cfg(feature = "x")` always evaluate to false, but will instead be
+    /// matched against the specified cfgs. Note that `test`, `debug_assertions`
+    /// and `proc_macro` are also treated as flags.
+    ///
+    /// ```
+    /// # use krates::{Builder, Cfg};
+    /// Builder::new().include_targets_with_cfgs([(
+    ///     "x86_64-unknown-linux-gnu",
+    ///     vec![],
+    ///     vec![
+    ///         "tokio_unstable".parse::<Cfg>().unwrap(),
+    ///         Cfg::KeyValue {
+    ///             key: "docsrs".to_owned(),
+    ///             value: "yes".to_owned(),
+    ///         },
+    ///     ],
+    /// )]);
+    /// ```
+    pub fn include_targets_with_cfgs<S: Into<Target>>(
+        &mut self,
+        targets: impl IntoIterator<Item = (S, Vec<String>, Vec<Cfg>)>,
+    ) -> &mut Self {
+        self.target_filters
+            .extend(
+                targets
+                    .into_iter()
+                    .map(|(triple, features, cfgs)| TargetFilter {
+                        inner: triple.into(),
+                        features,
+                        cfgs,
+                    }),
+            );
+        self
+    }
+
+    /// Sets a callback that is invoked with each [`Diagnostic`] encountered
+    /// while building the graph. Diagnostics are not errors, but inform why
+    /// the graph might not look the way it was expected to, eg. when a
+    /// dependency was filtered by a `target_feature` that was not specified.
+    ///
+    /// ```
+    /// # use krates::Builder;
+    /// Builder::new().on_diagnostic(|diag| eprintln!("warning: {diag}"));
+    /// ```
+    pub fn on_diagnostic<F>(&mut self, on_diagnostic: F) -> &mut Self
+    where
+        F: FnMut(Diagnostic) + Send + 'static,
+    {
+        self.on_diagnostic = Some(Box::new(on_diagnostic));
+        self
+    }
+
     /// Configures the index implementation
     ///
     /// This method allows overriding the location of your `CARGO_HOME`, but note
@@ -570,9 +954,28 @@ impl Builder {
         N: From<crate::Package>,
         E: From<Edge>,
         F: OnFilter,
+    {
+        self.build_with_factories(cmd, on_filter, DefaultFactory, DefaultFactory)
+    }
+
+    /// Builds a [`Krates`] graph using the metadata retrieved via the specified
+    /// metadata command, the same as [`Builder::build`], except that each crate
+    /// node and edge is created via the specified factories, see
+    /// [`Builder::build_with_metadata_and_factories`]
+    pub fn build_with_factories<N, E, F, NF, EF>(
+        self,
+        cmd: impl Into<cm::MetadataCommand>,
+        on_filter: F,
+        node_factory: NF,
+        edge_factory: EF,
+    ) -> Result<Krates<N, E>, Error>
+    where
+        F: OnFilter,
+        NF: NodeFactory<N>,
+        EF: EdgeFactory<E>,
     {
         let metadata = cmd.into().exec()?;
-        self.build_with_metadata(metadata, on_filter)
+        self.build_with_metadata_and_factories(metadata, on_filter, node_factory, edge_factory)
     }
 
     /// Builds a [`Krates`] graph using the specified metadata. If `on_filter` is
@@ -600,12 +1003,66 @@ impl Builder {
     pub fn build_with_metadata<N, E, F>(
         self,
         md: crate::Metadata,
-        mut on_filter: F,
+        on_filter: F,
     ) -> Result<Krates<N, E>, Error>
     where
         N: From<crate::Package>,
         E: From<Edge>,
         F: OnFilter,
+    {
+        self.build_with_metadata_and_factories(md, on_filter, DefaultFactory, DefaultFactory)
+    }
+
+    /// Builds a [`Krates`] graph using the specified metadata, the same as
+    /// [`Builder::build_with_metadata`], except that each crate node and edge
+    /// is created via the specified factories, which are given additional
+    /// context about the crate or edge rather than just the package or edge.
+    ///
+    /// ```no_run
+    /// # use krates::{Builder, Edge, EdgeContext, Krates, NodeContext, NoneFilter, cm::Package};
+    /// struct Crate {
+    ///     name: String,
+    ///     is_workspace_member: bool,
+    ///     depth: usize,
+    /// }
+    ///
+    /// struct Dep {
+    ///     from: String,
+    ///     to: String,
+    ///     edge: Edge,
+    /// }
+    ///
+    /// let contents = std::fs::read_to_string("metadata.json").unwrap();
+    /// let md: krates::cm::Metadata = serde_json::from_str(&contents).unwrap();
+    ///
+    /// let krates: Krates<Crate, Dep> = Builder::new()
+    ///     .build_with_metadata_and_factories(
+    ///         md,
+    ///         NoneFilter,
+    ///         |ctx: NodeContext<'_>, pkg: Package| Crate {
+    ///             name: pkg.name,
+    ///             is_workspace_member: ctx.is_workspace_member,
+    ///             depth: ctx.depth,
+    ///         },
+    ///         |ctx: EdgeContext<'_>, edge: Edge| Dep {
+    ///             from: ctx.source.name.clone(),
+    ///             to: ctx.target.name.clone(),
+    ///             edge,
+    ///         },
+    ///     )
+    ///     .unwrap();
+    /// ```
+    pub fn build_with_metadata_and_factories<N, E, F, NF, EF>(
+        self,
+        md: crate::Metadata,
+        mut on_filter: F,
+        mut node_factory: NF,
+        mut edge_factory: EF,
+    ) -> Result<Krates<N, E>, Error>
+    where
+        F: OnFilter,
+        NF: NodeFactory<N>,
+        EF: EdgeFactory<E>,
     {
         let mut resolved = md.resolve.ok_or(Error::NoResolveGraph)?;
 
@@ -695,6 +1152,15 @@ impl Builder {
                 packages.iter().map(|(_id, pkg)| pkg.name.clone()).collect(),
             )
         }) {
+            #[cfg(feature = "parallel")]
+            {
+                use rayon::prelude::*;
+
+                packages
+                    .par_iter_mut()
+                    .for_each(|(_, krate)| index::fix_features(&index, krate));
+            }
+            #[cfg(not(feature = "parallel"))]
             for (_, krate) in &mut packages {
                 index::fix_features(&index, krate);
             }
@@ -710,6 +1176,13 @@ impl Builder {
 
         let root = resolved.root.take().map(Kid::from);
 
+        // Only available with cargo 1.71+
+        let default_members: Option<Vec<Kid>> = md.workspace_default_members.0.map(|dm| {
+            let mut dm: Vec<_> = dm.into_iter().map(Kid::from).collect();
+            dm.sort();
+            dm
+        });
+
         let roots = {
             let mut roots = BTreeSet::new();
 
@@ -717,38 +1190,59 @@ impl Builder {
             // any, this is to take into account scenarios where you have a large
             // workspace, but only want to get the crates used by a subset of the
             // workspace
-            if self.workspace_filters.is_empty() {
-                // If the resolve graph specifies a root, it means the user
-                // specified a particular crate in a workspace, so we'll only use
-                // that single root for the entire graph rather than a root for each
-                // workspace member crate
-                if !self.workspace
+            if self.workspace_filters.is_empty() && self.workspace_specs.is_empty() {
+                if self.default_members {
+                    // Mirror what a plain `cargo build` in the root of the
+                    // workspace would build
+                    if let Some(dm) = &default_members {
+                        roots.extend(
+                            workspace_members
+                                .iter()
+                                .filter(|wm| dm.binary_search(wm).is_ok()),
+                        );
+                    }
+                } else if !self.workspace
                     && let Some(rkid) = &root
                 {
+                    // If the resolve graph specifies a root, it means the user
+                    // specified a particular crate in a workspace, so we'll only use
+                    // that single root for the entire graph rather than a root for each
+                    // workspace member crate
                     roots.insert(rkid);
                 }
 
                 if roots.is_empty() {
                     roots.extend(workspace_members.iter());
                 }
-            } else {
+            } else if self.workspace_specs.is_empty()
+                && self.workspace_filters.len() == 1
+                && Some(md.workspace_root.as_ref()) == self.workspace_filters[0].parent()
+            {
                 // If the filters only contain 1 path, and it is the path to a
                 // workspace toml, then we disregard the filters
-                if self.workspace_filters.len() == 1
-                    && Some(md.workspace_root.as_ref()) == self.workspace_filters[0].parent()
-                {
-                    roots.extend(workspace_members.iter());
-                } else {
-                    for wm in &workspace_members {
-                        if let Ok(i) = packages.binary_search_by(|(id, _pkg)| id.cmp(wm)) {
-                            let mp = packages[i].1.manifest_path.as_std_path();
-                            let manifest_path = mp.canonicalize();
-
-                            let mp = manifest_path.as_deref().unwrap_or(mp);
-                            if self.workspace_filters.iter().any(|wf| wf == mp) {
-                                roots.insert(wm);
-                            }
-                        }
+                roots.extend(workspace_members.iter());
+            } else {
+                for wm in &workspace_members {
+                    let Ok(i) = packages.binary_search_by(|(id, _pkg)| id.cmp(wm)) else {
+                        continue;
+                    };
+
+                    let pkg = &packages[i].1;
+                    if self.workspace_specs.iter().any(|spec| spec.matches(pkg)) {
+                        roots.insert(wm);
+                        continue;
+                    }
+
+                    if self.workspace_filters.is_empty() {
+                        continue;
+                    }
+
+                    let mp = pkg.manifest_path.as_std_path();
+                    let manifest_path = mp.canonicalize();
+
+                    let mp = manifest_path.as_deref().unwrap_or(mp);
+                    if self.workspace_filters.iter().any(|wf| wf == mp) {
+                        roots.insert(wm);
                     }
                 }
             }
@@ -756,9 +1250,10 @@ impl Builder {
             roots
         };
 
-        let is_root_crate = |pid: &Kid| -> bool { roots.contains(&pid) };
-
         let exclude = self.exclude;
+        let mut on_diagnostic = self.on_diagnostic;
+        let mut diagnostics = Diagnostics::new(on_diagnostic.is_some());
+        let root_features = self.root_features;
 
         let include_all_targets = self.target_filters.is_empty();
         let ignore_kinds = self.ignore_kinds;
@@ -777,6 +1272,9 @@ impl Builder {
             /// The name of the dependency, which could be a different name than the crate itself
             name: String,
             pkg: Kid,
+            /// The index of the dependency's node, so that it doesn't need to
+            /// be looked up by its id every time it is visited
+            index: usize,
             version: semver::Version,
             dep_kinds: Vec<DepKindInfo>,
         }
@@ -800,9 +1298,116 @@ impl Builder {
             false
         }
 
+        /// Finds the index of the dependency declared in the crate's manifest
+        /// that resulted in the resolved dependency of the specified kind
+        fn declared_dependency(
+            krate: &crate::Package,
+            rdep: &NodeDep,
+            dk: &DepKindInfo,
+            is_opaque: bool,
+        ) -> Option<usize> {
+            let maybe_real_name = rdep.pkg.name();
+
+            // If there are multiple versions of the same package we use the
+            // version to disambiguate references to them, unfortunately,
+            // though (it should be) extremely rare to do this, we always
+            // check that versions match once a crate is found, as nodes can
+            // not be resolved due to features, but will still be listed as
+            // a dependency
+            //
+            // Note that cargo _should_ fail to resolve nodes if the same
+            // package is referenced with two `^` (compatible) semvers, ie,
+            // you can't reference both ">= 0.2.12" and "=0.2.7" of a package
+            // even if they could never point to the same package, this _may_
+            // mean there could be a situation where a single crate _could_
+            // be referenced with 0.0.x versions, but...I'll fix that if
+            // someone reports an issue
+            let rdep_version = &rdep.version;
+            let has_prelease = !rdep_version.pre.is_empty();
+
+            let is_candidate = |dep: &cm::Dependency| {
+                if dk.kind != dep.kind {
+                    return false;
+                }
+
+                // Crates can rename the dependency package themselves
+                let dname = dep.rename.as_deref().unwrap_or(&dep.name);
+                if maybe_real_name != dname && !dep_names_match(dname, rdep) {
+                    return false;
+                }
+
+                // Handle case where a dependency may not have a version requirement, which
+                // typically happens in the case of non-registry dependencies that use a pre-release
+                // semver, if the version _is_ a prelease it will never match the empty
+                // requirement. Note this will pass through eg. path dependencies that don't specify a
+                // version, but those should be rejected during source comparison if matching against eg. a registry crate
+                if !((has_prelease && dep.req.comparators.is_empty())
+                    || dep.req.matches(rdep_version))
+                {
+                    return false;
+                }
+
+                crate::targets_eq(&dk.cfg, &dep.target)
+            };
+
+            let mut candidates = krate
+                .dependencies
+                .iter()
+                .enumerate()
+                .filter(|(_, dep)| is_candidate(dep));
+
+            let first = candidates.next()?;
+            if candidates.next().is_none() {
+                return Some(first.0);
+            }
+
+            krate
+                .dependencies
+                .iter()
+                .enumerate()
+                .filter(|(_, dep)| is_candidate(dep))
+                .find_map(|(dep_index, dep)| {
+                    // Finally, even if the name matches and the version matches, the source for the package might
+                    // be different if there are multiple git dependencies at different revisions :(, or if
+                    // There is also an _extreme_ edge case where a package's lib target can be the same
+                    // name as another package. This actually would mean that the code won't compile, but I
+                    // encountered it in testing (eg. the `md-5` crate names its lib target `md5`, and you
+                    // can have a dependency on the `md5` crate, they both get resolved to the same name, but
+                    // then rustc can't compile `md5::compute` because there are two libs that satisfy that name)
+                    let matches = if let Some(dsrc) = &dep.source {
+                        let psrc = rdep.pkg.source();
+                        if let Some((dgit, pgit)) =
+                            dsrc.strip_prefix("git+").zip(psrc.strip_prefix("git+"))
+                        {
+                            // The opaque git sources can have the full revision spec at the end, which is not part of
+                            // source declaration
+                            let dgit = if is_opaque {
+                                dgit.rfind('#').map_or(dgit, |end| &dgit[..end])
+                            } else {
+                                dgit
+                            };
+
+                            dgit == pgit
+                        } else {
+                            dsrc == psrc
+                        }
+                    } else if let Some(dpath) = &dep.path {
+                        compare_paths(dpath, rdep.pkg.source())
+                    } else {
+                        false
+                    };
+
+                    matches.then_some(dep_index)
+                })
+        }
+
         #[derive(Debug)]
         struct Node {
             id: Kid,
+            /// The index of the crate's package. The metadata can contain
+            /// packages that aren't part of the resolve graph, so this can
+            /// differ from the index of the node itself
+            package: usize,
             deps: Vec<NodeDep>,
             features: Vec<String>,
             has_default_feature: bool,
@@ -810,16 +1415,30 @@ impl Builder {
 
         impl Node {
             #[inline]
-            fn feature_index(&self, feat: &str) -> usize {
-                match self.features.binary_search_by(|f| f.as_str().cmp(feat)) {
-                    Ok(i) => i,
-                    Err(_i) => {
-                        unreachable!(
-                            "unable to locate {feat} for crate {} features({:?})",
-                            self.id, self.features
-                        );
-                    }
+            fn feature_index(&self, feat: &str) -> Option<usize> {
+                self.features
+                    .binary_search_by(|f| f.as_str().cmp(feat))
+                    .ok()
+            }
+
+            /// Gets the index of the feature, adding a diagnostic if it was
+            /// not one of the features resolved for the crate
+            #[inline]
+            fn feature_index_or_diag(
+                &self,
+                feat: &str,
+                diagnostics: &mut Diagnostics,
+            ) -> Option<usize> {
+                let index = self.feature_index(feat);
+
+                if index.is_none() {
+                    diagnostics.push(Diagnostic::UnknownFeature {
+                        krate: self.id.clone(),
+                        feature: feat.to_owned(),
+                    });
                 }
+
+                index
             }
 
             #[inline]
@@ -830,19 +1449,19 @@ impl Builder {
 
         // We need to establish if the package ids are in the opaque or stable format as
         // it changes how git sources are compared
-        let is_opaque = resolved.nodes[0].id.repr.splitn(3, ' ').count() == 3;
+        let is_opaque = resolved
+            .nodes
+            .first()
+            .is_some_and(|rn| rn.id.repr.splitn(3, ' ').count() == 3);
 
         let mut nodes: Vec<_> = resolved
             .nodes
             .into_iter()
             .map(|rn| {
                 let id = Kid::from(rn.id);
-                if let Err(i) = packages.binary_search_by(|(pid, _)| pid.cmp(&id)) {
-                    unreachable!(
-                        "oh no, it looks we couldn't find '{id}', maybe it is this? {:#?}",
-                        &packages[i]
-                    );
-                }
+                let Ok(package) = packages.binary_search_by(|(pid, _)| pid.cmp(&id)) else {
+                    return Err(Error::MissingPackage(id));
+                };
 
                 let mut deps: Vec<_> = rn
                     .deps
@@ -862,22 +1481,20 @@ impl Builder {
                         let version = match pkg.version().parse() {
                             Ok(v) => v,
                             Err(err) => {
-                                panic!(
-                                    "failed to parse version '{}' from '{}': {err}",
-                                    pkg.version(),
-                                    pkg.repr
-                                );
+                                return Err(Error::InvalidVersion { krate: pkg, err });
                             }
                         };
 
-                        NodeDep {
+                        Ok(NodeDep {
                             name: dn.name,
                             pkg,
+                            // Filled in once every node has been collected
+                            index: usize::MAX,
                             version,
                             dep_kinds,
-                        }
+                        })
                     })
-                    .collect();
+                    .collect::<Result<_, _>>()?;
 
                 // These _should_ always already be sorted, but again, might be
                 // due to implementation details rather than guaranteed
@@ -896,19 +1513,61 @@ impl Builder {
                     .binary_search_by(|f| f.as_str().cmp("default"))
                     .is_ok();
 
-                Node {
+                Ok(Node {
                     id,
+                    package,
                     deps,
                     features,
                     has_default_feature,
-                }
+                })
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
 
         nodes.sort_by(|a, b| a.id.cmp(&b.id));
 
-        let mut dep_edge_map = BTreeMap::new();
-        let mut feature_edge_map = BTreeMap::new();
+        // Every crate is referred to by the index of its node from here on,
+        // which preserves the ordering of their ids, so ensure that every
+        // dependency and root is actually present in the resolve graph
+        let node_index = |kid: &Kid| -> Result<usize, Error> {
+            nodes
+                .binary_search_by(|n| n.id.cmp(kid))
+                .map_err(|_i| Error::MissingPackage(kid.clone()))
+        };
+
+        let dep_indices = nodes
+            .iter()
+            .map(|node| {
+                node.deps
+                    .iter()
+                    .map(|dep| node_index(&dep.pkg))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let roots = roots
+            .into_iter()
+            .map(node_index)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let is_root_crate = |index: usize| -> bool { roots.binary_search(&index).is_ok() };
+
+        if let Some(kid) = root_features
+            .keys()
+            .find(|kid| node_index(kid).map_or(true, |i| !is_root_crate(i)))
+        {
+            return Err(Error::UnknownRoot(kid.clone()));
+        }
+
+        for (node, indices) in nodes.iter_mut().zip(dep_indices) {
+            for (dep, index) in node.deps.iter_mut().zip(indices) {
+                dep.index = index;
+            }
+        }
+
+        let mut dep_edge_map: Vec<Option<PackageNode>> = Vec::new();
+        dep_edge_map.resize_with(nodes.len(), || None);
+        let mut feature_edge_map: Vec<Option<KrateFeatures>> = Vec::new();
+        feature_edge_map.resize_with(nodes.len(), || None);
 
         // The stack of pid + features that are visited until we've reached every
         // unique leaf.
@@ -919,23 +1578,23 @@ impl Builder {
         // entirely, and thus have to basically re-resolve the crates + features
         // to avoid adding nodes/edges that shouldn't exist based on the caller's
         // configuration (eg. https://github.com/EmbarkStudios/krates/issues/60)
-        struct VisitStack<'s> {
-            v: Vec<(&'s Kid, Option<usize>)>,
+        struct VisitStack {
+            v: Vec<(usize, Option<usize>)>,
         }
 
-        impl<'s> VisitStack<'s> {
+        impl VisitStack {
             #[inline]
-            fn push(&mut self, kid: &'s Kid) {
-                self.v.push((kid, None));
+            fn push(&mut self, krate: usize) {
+                self.v.push((krate, None));
             }
 
             #[inline]
-            fn push_with_feature(&mut self, kid: &'s Kid, feat: usize) {
-                self.v.push((kid, Some(feat)));
+            fn push_with_feature(&mut self, krate: usize, feat: usize) {
+                self.v.push((krate, Some(feat)));
             }
 
             #[inline]
-            fn pop(&mut self) -> Option<(&'s Kid, Option<usize>)> {
+            fn pop(&mut self) -> Option<(usize, Option<usize>)> {
                 self.v.pop()
             }
         }
@@ -944,29 +1603,59 @@ impl Builder {
             v: Vec::with_capacity(roots.len()),
         };
 
-        for root in &roots {
-            let krate_index = nodes.binary_search_by(|n| n.id.cmp(root)).unwrap();
-            let rnode = &nodes[krate_index];
+        // The features enabled on each root, which are either all of the
+        // features cargo resolved for it, or the user's selection
+        let mut root_seeds = BTreeMap::new();
 
-            visit_stack.push(root);
+        for &root in &roots {
+            let rnode = &nodes[root];
 
-            for feat in 0..rnode.features.len() {
-                visit_stack.push_with_feature(root, feat);
-            }
-        }
+            let seeds: Vec<usize> = if let Some((features, default)) = root_features.get(&rnode.id)
+            {
+                let mut seeds = Vec::with_capacity(features.len() + 1);
 
-        #[derive(Debug)]
-        struct DependencyEdge {
+                if *default && let Some(i) = rnode.feature_index("default") {
+                    seeds.push(i);
+                }
+
+                for feature in features {
+                    let Ok(i) = rnode.features.binary_search_by(|f| f.as_str().cmp(feature)) else {
+                        return Err(Error::UnknownRootFeature {
+                            krate: rnode.id.clone(),
+                            feature: feature.clone(),
+                        });
+                    };
+
+                    seeds.push(i);
+                }
+
+                seeds
+            } else {
+                (0..rnode.features.len()).collect()
+            };
+
+            visit_stack.push(root);
+
+            for feat in &seeds {
+                visit_stack.push_with_feature(root, *feat);
+            }
+
+            root_seeds.insert(root, seeds);
+        }
+
+        #[derive(Debug)]
+        struct DependencyEdge {
             kind: DepKind,
             cfg: Option<String>,
             features: Vec<usize>,
-            dep_index: usize,
+            declaration: Arc<crate::DepDeclaration>,
         }
 
         #[derive(Debug)]
-        struct PackageNode<'p> {
+        struct PackageNode {
             is_root: bool,
-            deps: BTreeMap<&'p Kid, KrateDependency>,
+            /// The dependencies, keyed by the index of their node
+            deps: BTreeMap<usize, KrateDependency>,
         }
 
         #[derive(Debug)]
@@ -983,26 +1672,34 @@ impl Builder {
         }
 
         #[derive(Debug)]
-        struct FeatureEdge<'nodes> {
-            kid: &'nodes Kid,
+        struct FeatureEdge {
+            /// The index of the node of the crate the feature belongs to
+            krate: usize,
             name: FeatureEdgeName,
+            /// True if this is a `krate?/feature`, which only enables the
+            /// feature if the dependency is enabled by something else
+            weak: bool,
+            /// False if the edge is only used when resolving the features
+            /// for each unit, as the dependency of a `krate?/feature` that
+            /// is only enabled via `dep:krate` is linked from the crate instead
+            unified: bool,
         }
 
         #[derive(Debug)]
-        struct KrateFeatures<'p> {
+        struct KrateFeatures {
             /// The inner DAG of features, note these are _all_ resolved features,
             /// but might not be actually be present in the final graph if they
             /// reference pruned krates
-            graph: Vec<(String, Vec<FeatureEdge<'p>>)>,
+            graph: Vec<(String, Vec<FeatureEdge>)>,
             /// The actual set of features enabled by 1 or more parent krates
             actual: BTreeSet<usize>,
             /// Weakly referenced features
-            pending_weak: BTreeMap<&'p Kid, BTreeSet<usize>>,
+            pending_weak: BTreeMap<usize, BTreeSet<usize>>,
             filled_non_optional: bool,
         }
 
-        let check = |map: &BTreeMap<&Kid, KrateFeatures<'_>>, pid: &Kid, feature: Option<usize>| {
-            map.get(pid).is_some_and(|pn| {
+        let check = |map: &[Option<KrateFeatures>], pid: usize, feature: Option<usize>| {
+            map[pid].as_ref().is_some_and(|pn| {
                 if let Some(feat) = feature {
                     pn.actual.contains(&feat)
                 } else {
@@ -1011,35 +1708,137 @@ impl Builder {
             })
         };
 
-        let get_rnode = |pid: &Kid| &nodes[nodes.binary_search_by(|n| n.id.cmp(pid)).unwrap()];
+        #[derive(Clone, Debug)]
+        struct DepKindMatch {
+            /// The index of the dependency declared in the crate's manifest
+            declared: Option<usize>,
+            /// True if the dependency kind's cfg matched the target filters
+            included: bool,
+            /// True if a non-target predicate decided if the dependency kind
+            /// was included or not
+            cfg_decided: bool,
+        }
 
-        while let Some((pid, feature)) = visit_stack.pop() {
-            if check(&feature_edge_map, pid, feature) {
-                continue;
-            }
+        let match_dependencies = |rnode: &Node, krate: &crate::Package| -> Vec<Vec<DepKindMatch>> {
+            rnode
+                .deps
+                .iter()
+                .map(|rdep| {
+                    rdep.dep_kinds
+                        .iter()
+                        .map(|dk| {
+                            let declared = declared_dependency(krate, rdep, dk, is_opaque);
+                            let Some(cfg) = &dk.cfg else {
+                                return DepKindMatch {
+                                    declared,
+                                    included: true,
+                                    cfg_decided: false,
+                                };
+                            };
 
-            let is_in_workspace = workspace_members.binary_search(pid).is_ok();
+                            let mut cfg_decided = false;
+
+                            let included = if !include_all_targets {
+                                if let Some(expr) = &cfg.cfg {
+                                    // We only need to focus on target predicates because they are
+                                    // the only type of predicate allowed by cargo at the moment
+
+                                    // While it might be nicer to evaluate all the targets for each predicate
+                                    // it would lead to weird situations where an expression could evaluate to true
+                                    // (or false) with a combination of platform, that would otherwise be impossible,
+                                    // eg cfg(all(windows, target_env = "musl")) could evaluate to true
+                                    let matched = targets
+                                        .iter()
+                                        .any(|target| expr.eval(|pred| target.eval(pred)));
+
+                                    // Predicates other than the target ones, eg. target_feature, are either
+                                    // rare or cannot be known without the user telling us, so let them know
+                                    // if one of them was the reason the dependency was included or not
+                                    let uncertain = |assume: bool| {
+                                        targets.iter().any(|target| {
+                                            expr.eval(|pred| match pred {
+                                                cfg_expr::Predicate::Target(_) => target.eval(pred),
+                                                _ => assume,
+                                            })
+                                        })
+                                    };
+
+                                    cfg_decided = uncertain(true) != uncertain(false);
+                                    matched
+                                } else {
+                                    // If it's not a cfg expression, it's just a fully specified target triple,
+                                    // so we just do a string comparison
+                                    targets
+                                        .iter()
+                                        .any(|target| target.matches_triple(&cfg.inner))
+                                }
+                            } else if let Some(expr) = &cfg.cfg {
+                                // This is _basically_ a tortured way to evaluate `cfg(any())`, which is always false but
+                                // is used by eg. serde -> serde_derive. If not filtering targets this would mean that
+                                // serde_derive and all of its dependencies would be pulled into the graph, even if the
+                                // only edge was the cfg(any()).
+
+                                // We can't just do an eval and always return true, as that then would cause any
+                                // not() expressions to evaluate to false
+                                expr.predicates().count() != 0 || expr.eval(|_| true)
+                            } else {
+                                true
+                            };
 
-            let krate_index = nodes.binary_search_by(|n| n.id.cmp(pid)).unwrap();
+                            DepKindMatch {
+                                declared,
+                                included,
+                                cfg_decided,
+                            }
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+
+        // Crates are visited once for each of their enabled features, so we
+        // only match each resolved dependency kind to the dependency declared
+        // in the manifest, and evaluate its cfg, once per crate rather than
+        // every time it is visited. When building in parallel, every crate is
+        // matched up front as they are independent of each other
+        #[cfg(feature = "parallel")]
+        let mut dep_tables: Vec<_> = {
+            use rayon::prelude::*;
+
+            nodes
+                .par_iter()
+                .map(|rnode| Some(match_dependencies(rnode, &packages[rnode.package].1)))
+                .collect()
+        };
+        #[cfg(not(feature = "parallel"))]
+        let mut dep_tables = vec![None; nodes.len()];
+
+        while let Some((krate_index, feature)) = visit_stack.pop() {
+            if check(&feature_edge_map, krate_index, feature) {
+                continue;
+            }
 
             let rnode = &nodes[krate_index];
-            let (_, krate) = &packages[krate_index];
+            let pid = &rnode.id;
+
+            let is_in_workspace = workspace_members.binary_search(pid).is_ok();
+            let (_, krate) = &packages[rnode.package];
 
             if exclude.iter().any(|exc| exc.matches(krate)) {
                 continue;
             }
 
-            let get_dep_id = |dep_name: &str| -> Option<&Kid> {
-                rnode.deps.iter().find_map(|ndep| {
-                    if dep_names_match(dep_name, ndep) {
-                        Some(&ndep.pkg)
-                    } else {
-                        None
-                    }
-                })
+            let dep_table =
+                dep_tables[krate_index].get_or_insert_with(|| match_dependencies(rnode, krate));
+
+            let get_dep = |dep_name: &str| -> Option<&NodeDep> {
+                rnode
+                    .deps
+                    .iter()
+                    .find(|ndep| dep_names_match(dep_name, ndep))
             };
 
-            let krate_features = feature_edge_map.entry(pid).or_insert_with(|| {
+            let krate_features = feature_edge_map[krate_index].get_or_insert_with(|| {
                 // Cargo puts out a flat list of the enabled features, but we need
                 // to use the declared features on the crate itself to figure out
                 // the actual chain of features from one crate to another
@@ -1108,46 +1907,44 @@ impl Builder {
 
                                 match sf.feat() {
                                     Feature::Krate(krate_name) => {
-                                        let kid = get_dep_id(krate_name)?;
-                                        let real_name = kid.name();
+                                        let ndep = get_dep(krate_name)?;
+                                        let real_name = ndep.pkg.name();
 
                                         Some(FeatureEdge {
-                                            kid,
+                                            krate: ndep.index,
                                             name: if real_name != krate_name {
                                                 FeatureEdgeName::Rename(krate_name.to_owned())
                                             } else {
                                                 FeatureEdgeName::Krate
                                             },
+                                            weak: false,
+                                            unified: true,
                                         })
                                     }
                                     Feature::Simple(s) => Some(FeatureEdge {
-                                        kid: pid,
+                                        krate: krate_index,
                                         name: FeatureEdgeName::Feature(s.to_owned()),
+                                        weak: false,
+                                        unified: true,
                                     }),
                                     Feature::Strong {
                                         krate: krate_name,
                                         feature,
                                     } => Some(FeatureEdge {
-                                        kid: get_dep_id(krate_name)?,
+                                        krate: get_dep(krate_name)?.index,
                                         name: FeatureEdgeName::Feature(feature.to_owned()),
+                                        weak: false,
+                                        unified: true,
                                     }),
                                     Feature::Weak {
                                         krate: krate_name,
                                         feature,
-                                    } => {
-                                        if rnode.features.iter().any(|kn| kn == krate_name) {
-                                            Some(FeatureEdge {
-                                                kid: get_dep_id(krate_name)?,
-                                                name: FeatureEdgeName::Feature(feature.to_owned()),
-                                            })
-                                        } else {
-                                            None
-                                        }
-                                        // } => Some(FeatureEdge {
-                                        //     kid: get_dep_id(krate_name)?,
-                                        //     name: FeatureEdgeName::Feature(feature.to_owned()),
-                                        // }),
-                                    }
+                                    } => Some(FeatureEdge {
+                                        krate: get_dep(krate_name)?.index,
+                                        name: FeatureEdgeName::Feature(feature.to_owned()),
+                                        weak: true,
+                                        unified: rnode.features.iter().any(|kn| kn == krate_name),
+                                    }),
                                 }
                             })
                             .collect();
@@ -1164,12 +1961,12 @@ impl Builder {
                 }
             });
 
-            let pn = dep_edge_map.entry(pid).or_insert_with(|| PackageNode {
-                is_root: is_root_crate(pid),
+            let pn = dep_edge_map[krate_index].get_or_insert_with(|| PackageNode {
+                is_root: is_root_crate(krate_index),
                 deps: BTreeMap::new(),
             });
 
-            let mut deps = BTreeMap::<&Kid, (&NodeDep, Option<BTreeSet<usize>>)>::new();
+            let mut deps = BTreeMap::<usize, (&NodeDep, Option<BTreeSet<usize>>)>::new();
 
             if let Some(feature) = feature {
                 if !krate_features.actual.insert(feature) {
@@ -1177,7 +1974,7 @@ impl Builder {
                 }
 
                 if !krate_features.filled_non_optional {
-                    visit_stack.push(pid);
+                    visit_stack.push(krate_index);
                 }
 
                 // This _should_ never fail in normal cases, however if an
@@ -1186,7 +1983,10 @@ impl Builder {
                 // actual crate manifest
                 let feature_key = rnode.feature(feature);
                 let Some(fs) = krate.features.get(feature_key) else {
-                    // TODO: Show the user a warning, or just fail?
+                    diagnostics.push(Diagnostic::UnknownFeature {
+                        krate: pid.clone(),
+                        feature: feature_key.to_owned(),
+                    });
                     continue;
                 };
 
@@ -1195,7 +1995,9 @@ impl Builder {
 
                     let (krate_name, feature) = match pf.feat() {
                         Feature::Simple(feat) => {
-                            visit_stack.push_with_feature(pid, rnode.feature_index(feat));
+                            if let Some(fi) = rnode.feature_index_or_diag(feat, &mut diagnostics) {
+                                visit_stack.push_with_feature(krate_index, fi);
+                            }
                             continue;
                         }
                         Feature::Krate(krate) => (krate, None),
@@ -1216,21 +2018,36 @@ impl Builder {
                         continue;
                     };
 
-                    let rdep_node = get_rnode(&ndep.pkg);
+                    let rdep_node = &nodes[ndep.index];
                     let is_weak = matches!(pf.feat(), Feature::Weak { .. });
 
-                    if is_weak && !pn.deps.contains_key(&ndep.pkg) {
-                        let feature = rdep_node.feature_index(feature.unwrap());
-                        krate_features
-                            .pending_weak
-                            .entry(&ndep.pkg)
-                            .or_default()
-                            .insert(feature);
+                    // Only resolve the dependency's feature once, as it's
+                    // possible it was not resolved by cargo
+                    let feature = match feature {
+                        Some(feature) => {
+                            let Some(fi) =
+                                rdep_node.feature_index_or_diag(feature, &mut diagnostics)
+                            else {
+                                continue;
+                            };
+                            Some(fi)
+                        }
+                        None => None,
+                    };
+
+                    if is_weak && !pn.deps.contains_key(&ndep.index) {
+                        if let Some(feature) = feature {
+                            krate_features
+                                .pending_weak
+                                .entry(ndep.index)
+                                .or_default()
+                                .insert(feature);
+                        }
                         continue;
                     }
 
-                    if let Some(mut pending) = krate_features.pending_weak.remove(&ndep.pkg) {
-                        deps.entry(&ndep.pkg)
+                    if let Some(mut pending) = krate_features.pending_weak.remove(&ndep.index) {
+                        deps.entry(ndep.index)
                             .or_insert_with(|| (ndep, Some(BTreeSet::new())))
                             .1
                             .as_mut()
@@ -1239,29 +2056,27 @@ impl Builder {
                     }
 
                     let feats = deps
-                        .entry(&ndep.pkg)
+                        .entry(ndep.index)
                         .or_insert_with(|| (ndep, Some(BTreeSet::new())))
                         .1
                         .as_mut()
                         .unwrap();
 
                     if let Some(feature) = feature {
-                        feats.insert(rdep_node.feature_index(feature));
+                        feats.insert(feature);
                     }
                 }
             } else {
                 krate_features.filled_non_optional = true;
-                deps.extend(rnode.deps.iter().map(|ndep| (&ndep.pkg, (ndep, None))));
+                deps.extend(rnode.deps.iter().map(|ndep| (ndep.index, (ndep, None))));
             }
 
-            let targets = &targets;
-
             // Though each unique dependency can only be resolved once, it's possible
             // for the crate to list the same dependency multiple times, with different
             // dependency kinds, or different target configurations, so each one gets its
             // own edge
-            for (pkg, (rdep, mut features)) in deps {
-                let rdep_node = get_rnode(pkg);
+            for (dep_index, (rdep, mut features)) in deps {
+                let rdep_node = &nodes[dep_index];
 
                 #[derive(Debug)]
                 struct Edge<'d> {
@@ -1272,179 +2087,70 @@ impl Builder {
                     uses_default_features: bool,
                 }
 
-                let maybe_real_name = pkg.name();
                 let strong = features.is_some();
 
-                // If there are multiple versions of the same package we use the
-                // version to disambiguate references to them, unfortunately,
-                // though (it should be) extremely rare to do this, we always
-                // check that versions match once a crate is found, as nodes can
-                // not be resolved due to features, but will still be listed as
-                // a dependency
-                //
-                // Note that cargo _should_ fail to resolve nodes if the same
-                // package is referenced with two `^` (compatible) semvers, ie,
-                // you can't reference both ">= 0.2.12" and "=0.2.7" of a package
-                // even if they could never point to the same package, this _may_
-                // mean there could be a situation where a single crate _could_
-                // be referenced with 0.0.x versions, but...I'll fix that if
-                // someone reports an issue
-                let rdep_version = &rdep.version;
-                let has_prelease = !rdep_version.pre.is_empty();
-
-                let edges = rdep.dep_kinds.iter().filter_map(|dk| {
-                        let mask = match dk.kind {
-                            DepKind::Normal => 0x1,
-                            DepKind::Dev => 0x8,
-                            DepKind::Build => 0x40,
-                        };
-
-                        let mask = mask | (mask << if is_in_workspace { 1 } else { 2 });
-                        if mask & ignore_kinds == mask {
-                            return None;
-                        }
-
-                        let multiple_candidates = krate.dependencies.iter().filter(|dep| {
-                            if dk.kind != dep.kind {
-                                return false;
-                            }
-
-                            // Crates can rename the dependency package themselves
-                            let dname = dep.rename.as_deref().unwrap_or(&dep.name);
-                            if maybe_real_name != dname && !dep_names_match(dname, rdep) {
-                                return false;
-                            }
-
-                            // Handle case where a dependency may not have a version requirement, which
-                            // typically happens in the case of non-registry dependencies that use a pre-release
-                            // semver, if the version _is_ a prelease it will never match the empty
-                            // requirement
-                            if !((has_prelease && dep.req.comparators.is_empty()) || dep.req.matches(rdep_version)) {
-                                return false;
-                            }
-
-                            crate::targets_eq(&dk.cfg, &dep.target)
-                        }).count() > 1;
-
-                        let Some((dep_index, dep)) = krate
-                            .dependencies
-                            .iter()
-                            .enumerate()
-                            .find(|(_, dep)| {
-                                if dk.kind != dep.kind {
-                                    return false;
-                                }
-
-
-                                // Crates can rename the dependency package themselves
-                                let dname = dep.rename.as_deref().unwrap_or(&dep.name);
-                                if maybe_real_name != dname && !dep_names_match(dname, rdep) {
-                                    return false;
-                                }
-
-                                // Handle case where a dependency may not have a version requirement, which
-                                // typically happens in the case of non-registry dependencies that use a pre-release
-                                // semver, if the version _is_ a prelease it will never match the empty
-                                // requirement. Note this will pass through eg. path dependencies that don't specify a
-                                // version, but those should be rejected during source comparison if matching against eg. a registry crate
-                                if !((has_prelease && dep.req.comparators.is_empty()) || dep.req.matches(rdep_version)) {
-                                    return false;
-                                }
-
-                                if !crate::targets_eq(&dk.cfg, &dep.target) {
-                                    return false;
-                                }
-
-                                if !multiple_candidates {
-                                    return true;
-                                }
-
-                                // Finally, even if the name matches and the version matches, the source for the package might
-                                // be different if there are multiple git dependencies at different revisions :(, or if
-                                // There is also an _extreme_ edge case where a package's lib target can be the same
-                                // name as another package. This actually would mean that the code won't compile, but I
-                                // encountered it in testing (eg. the `md-5` crate names its lib target `md5`, and you
-                                // can have a dependency on the `md5` crate, they both get resolved to the same name, but
-                                // then rustc can't compile `md5::compute` because there are two libs that satisfy that name)
-                                if let Some(dsrc) = &dep.source {
-                                    let psrc = rdep.pkg.source();
-                                    let Some((dgit, pgit)) = dsrc.strip_prefix("git+").zip(psrc.strip_prefix("git+")) else {
-                                        return dsrc == psrc;
-                                    };
-
-                                    // The opaque git sources can have the full revision spec at the end, which is not part of
-                                    // source declaration
-                                    let dgit = if is_opaque {
-                                        dgit.rfind('#').map_or(dgit, |end| &dgit[..end])
-                                    } else {
-                                        dgit
-                                    };
-
-                                    dgit == pgit
-                                } else if let Some(dpath) = &dep.path {
-                                    compare_paths(dpath, rdep.pkg.source())
-                                } else {
-                                    false
-                                }
-                            }) else {
-                                unreachable!("cargo metadata resolved a dependency for a dependency not specified by the crate: {rdep:?}");
-                            };
+                let dep_matches = &dep_table[rnode
+                    .deps
+                    .binary_search_by(|ndep| ndep.index.cmp(&dep_index))
+                    .unwrap()];
+
+                let edges = rdep.dep_kinds.iter().enumerate().filter_map(|(dki, dk)| {
+                    let mask = match dk.kind {
+                        DepKind::Normal => 0x1,
+                        DepKind::Dev => 0x8,
+                        DepKind::Build => 0x40,
+                    };
 
-                        if dep.optional && !strong {
-                            return None;
-                        }
+                    let mask = mask | (mask << if is_in_workspace { 1 } else { 2 });
+                    if mask & ignore_kinds == mask {
+                        return None;
+                    }
 
-                        let cfg = if let Some(cfg) = &dk.cfg {
-                            if !include_all_targets {
-                                let matched = if let Some(expr) = &cfg.cfg {
-                                    // We only need to focus on target predicates because they are
-                                    // the only type of predicate allowed by cargo at the moment
+                    let dk_match = &dep_matches[dki];
+                    let Some(dep_index) = dk_match.declared else {
+                        diagnostics.push(Diagnostic::UnknownDependency {
+                            dependent: pid.clone(),
+                            dependency: rdep.pkg.clone(),
+                        });
+                        return None;
+                    };
 
-                                    // While it might be nicer to evaluate all the targets for each predicate
-                                    // it would lead to weird situations where an expression could evaluate to true
-                                    // (or false) with a combination of platform, that would otherwise be impossible,
-                                    // eg cfg(all(windows, target_env = "musl")) could evaluate to true
-                                    targets
-                                        .iter()
-                                        .any(|target| expr.eval(|pred| target.eval(pred)))
-                                } else {
-                                    // If it's not a cfg expression, it's just a fully specified target triple,
-                                    // so we just do a string comparison
-                                    targets.iter().any(|target| target.matches_triple(&cfg.inner))
-                                };
+                    let dep = &krate.dependencies[dep_index];
+                    if dep.optional && !strong {
+                        return None;
+                    }
 
-                                if !matched {
-                                    return None;
-                                }
-                            } else if let Some(expr) = &cfg.cfg {
-                                // This is _basically_ a tortured way to evaluate `cfg(any())`, which is always false but
-                                // is used by eg. serde -> serde_derive. If not filtering targets this would mean that
-                                // serde_derive and all of its dependencies would be pulled into the graph, even if the
-                                // only edge was the cfg(any()).
+                    if dk_match.cfg_decided
+                        && let Some(cfg) = &dk.cfg
+                    {
+                        let diag = Diagnostic::CfgDecidedEdge {
+                            dependent: pid.clone(),
+                            dependency: rdep.pkg.clone(),
+                            expression: cfg.inner.clone(),
+                            included: dk_match.included,
+                        };
 
-                                // We can't just do an eval and always return true, as that then would cause any
-                                // not() expressions to evaluate to false
-                                if expr.predicates().count() == 0 && !expr.eval(|_| true) {
-                                    return None;
-                                }
-                            }
+                        diagnostics.push(diag);
+                    }
 
-                            Some(cfg.inner.as_str())
-                        } else {
-                            None
-                        };
+                    if !dk_match.included {
+                        return None;
+                    }
 
-                        Some(Edge {
-                            kind: dk.kind,
-                            cfg,
-                            features: &dep.features,
-                            dep_index,
-                            // Dependencies will default to saying "uses_default_features" on edges,
-                            // even if the crate in question doesn't actually have a "default" feature,
-                            // so check that it actually does
-                            uses_default_features: dep.uses_default_features && rdep_node.has_default_feature,
-                        })
-                    });
+                    let cfg = dk.cfg.as_ref().map(|cfg| cfg.inner.as_str());
+
+                    Some(Edge {
+                        kind: dk.kind,
+                        cfg,
+                        features: &dep.features,
+                        dep_index,
+                        // Dependencies will default to saying "uses_default_features" on edges,
+                        // even if the crate in question doesn't actually have a "default" feature,
+                        // so check that it actually does
+                        uses_default_features: dep.uses_default_features
+                            && rdep_node.has_default_feature,
+                    })
+                });
 
                 // Don't add the dependency unless we have at least one edge, otherwise
                 // we could include optional dependencies that are only weakly referenced
@@ -1453,18 +2159,20 @@ impl Builder {
                     continue;
                 }
 
-                let dep = pn.deps.entry(pkg).or_insert_with(|| KrateDependency {
+                let dep = pn.deps.entry(dep_index).or_insert_with(|| KrateDependency {
                     edges: Vec::new(),
                     features: BTreeSet::new(),
                 });
 
-                let mut visit_dep = Some(pkg);
+                let mut visit_dep = Some(dep_index);
+                // The edges borrow the diagnostics until they are exhausted
+                let mut unknown_features = Diagnostics::new(on_diagnostic.is_some());
 
                 for edge in edges {
                     if let Some(features) = features.take() {
                         if let Some(vd) = visit_dep.take() {
                             visit_stack.push(vd);
-                            if let Some(kf) = krate_features.pending_weak.remove(vd) {
+                            if let Some(kf) = krate_features.pending_weak.remove(&vd) {
                                 for feat in kf {
                                     visit_stack.push_with_feature(vd, feat);
                                     dep.features.insert(feat);
@@ -1473,7 +2181,7 @@ impl Builder {
                         }
 
                         for feat in features {
-                            visit_stack.push_with_feature(pkg, feat);
+                            visit_stack.push_with_feature(dep_index, feat);
                             dep.features.insert(feat);
                         }
                     }
@@ -1488,7 +2196,7 @@ impl Builder {
 
                     if let Some(vd) = visit_dep.take() {
                         visit_stack.push(vd);
-                        if let Some(kf) = krate_features.pending_weak.remove(vd) {
+                        if let Some(kf) = krate_features.pending_weak.remove(&vd) {
                             for feat in kf {
                                 visit_stack.push_with_feature(vd, feat);
                                 dep.features.insert(feat);
@@ -1503,148 +2211,447 @@ impl Builder {
                         .map(|f| f.as_str())
                         .chain(edge.uses_default_features.then_some("default"))
                     {
-                        let feat_index = rdep_node.feature_index(feat);
-                        visit_stack.push_with_feature(pkg, feat_index);
+                        let Some(feat_index) =
+                            rdep_node.feature_index_or_diag(feat, &mut unknown_features)
+                        else {
+                            continue;
+                        };
+                        visit_stack.push_with_feature(dep_index, feat_index);
                         features.push(feat_index);
                     }
 
+                    let declared = &krate.dependencies[edge.dep_index];
                     let edge = DependencyEdge {
                         kind: edge.kind,
-                        dep_index: edge.dep_index,
                         cfg: edge.cfg.map(|s| s.into()),
                         features,
+                        declaration: Arc::new(crate::DepDeclaration {
+                            index: edge.dep_index,
+                            req: declared.req.clone(),
+                            rename: declared.rename.clone(),
+                            optional: declared.optional,
+                            uses_default_features: declared.uses_default_features,
+                            features: declared.features.clone(),
+                            registry: declared.registry.clone(),
+                        }),
                     };
 
                     dep.edges.push(edge);
                 }
+
+                for diag in unknown_features.found {
+                    diagnostics.push(diag);
+                }
             }
         }
 
         // Sanity check, it's possible the user could exclude all of the
         // possible workspace root nodes leaving themselves with an empty graph,
         // which isn't much use to anyone
-        if dep_edge_map.is_empty() {
+        if dep_edge_map.iter().all(Option::is_none) {
             return Err(Error::NoRootKrates);
         }
 
-        let mut graph = petgraph::Graph::<crate::Node<N>, E>::new();
-        graph.reserve_nodes(dep_edge_map.len());
+        // Calculate the minimum distance of every crate from any of the roots
+        let depths = {
+            let mut depths = vec![None; nodes.len()];
+            let mut queue: std::collections::VecDeque<_> = roots
+                .iter()
+                .filter(|root| dep_edge_map[**root].is_some())
+                .map(|root| (*root, 0))
+                .collect();
+
+            while let Some((pid, depth)) = queue.pop_front() {
+                if depths[pid].is_some() {
+                    continue;
+                }
+
+                depths[pid] = Some(depth);
+
+                if let Some(pn) = &dep_edge_map[pid] {
+                    queue.extend(
+                        pn.deps
+                            .keys()
+                            .filter(|dep| depths[**dep].is_none())
+                            .map(|dep| (*dep, depth + 1)),
+                    );
+                }
+            }
+
+            depths
+        };
+
+        // Remove every crate beyond the maximum depth, as well as the edges
+        // to them, so that they are reported as filtered
+        if let Some(max_depth) = self.max_depth {
+            let within = |pid: usize| depths[pid].is_some_and(|depth| depth <= max_depth);
+
+            for (pid, pn) in dep_edge_map.iter_mut().enumerate() {
+                if !within(pid) {
+                    *pn = None;
+                } else if let Some(pn) = pn {
+                    pn.deps.retain(|dep, _| within(*dep));
+                }
+            }
+        }
+
+        // Now that we know every edge that survived filtering, we can optionally
+        // re-resolve the features for each kind of compile unit a crate is built
+        // as, the same as cargo's v2 feature resolver, rather than the unified
+        // view that cargo metadata gives us
+        let mut unit_features = if self.unit_features {
+            use crate::units::{DepEdge, SubFeature, Unit, UnitGraph};
+
+            struct Units<'b> {
+                nodes: &'b [Node],
+                proc_macros: Vec<bool>,
+                dep_edge_map: &'b [Option<PackageNode>],
+                feature_edge_map: &'b [Option<KrateFeatures>],
+            }
+
+            impl UnitGraph for Units<'_> {
+                type Feature = usize;
+
+                fn is_proc_macro(&self, krate: usize) -> bool {
+                    self.proc_macros[krate]
+                }
+
+                fn dependencies(&self, krate: usize) -> impl Iterator<Item = usize> {
+                    self.dep_edge_map[krate]
+                        .iter()
+                        .flat_map(|pn| pn.deps.keys().copied())
+                }
+
+                fn edges(
+                    &self,
+                    krate: usize,
+                    dep: usize,
+                ) -> impl Iterator<Item = DepEdge<'_, usize>> {
+                    self.dep_edge_map[krate]
+                        .as_ref()
+                        .and_then(|pn| pn.deps.get(&dep))
+                        .into_iter()
+                        .flat_map(|dep| dep.edges.iter().enumerate())
+                        .map(|(id, edge)| DepEdge {
+                            id,
+                            kind: edge.kind,
+                            optional: edge.declaration.optional,
+                            features: &edge.features,
+                        })
+                }
+
+                fn sub_features(
+                    &self,
+                    krate: usize,
+                    feature: &usize,
+                ) -> Option<impl Iterator<Item = SubFeature<usize>>> {
+                    let feat = self.nodes[krate].feature(*feature);
+                    let sub_features = self.feature_edge_map[krate].as_ref().and_then(|kf| {
+                        kf.graph
+                            .binary_search_by(|(name, _)| name.as_str().cmp(feat))
+                            .ok()
+                            .map(|i| &kf.graph[i].1)
+                    });
+
+                    Some(sub_features.into_iter().flatten().filter_map(move |sf| {
+                        Some(match &sf.name {
+                            FeatureEdgeName::Feature(name) => {
+                                let fi = self.nodes[sf.krate].features.binary_search(name).ok()?;
+
+                                if sf.krate == krate {
+                                    SubFeature::Feature(fi)
+                                } else {
+                                    SubFeature::Dep {
+                                        krate: sf.krate,
+                                        feature: Some(fi),
+                                        strong: !sf.weak,
+                                    }
+                                }
+                            }
+                            FeatureEdgeName::Rename(_) | FeatureEdgeName::Krate => {
+                                SubFeature::Dep {
+                                    krate: sf.krate,
+                                    feature: None,
+                                    strong: true,
+                                }
+                            }
+                        })
+                    }))
+                }
+            }
+
+            let units = Units {
+                nodes: &nodes,
+                proc_macros: nodes
+                    .iter()
+                    .map(|rnode| {
+                        packages[rnode.package]
+                            .1
+                            .targets
+                            .iter()
+                            .any(|t| t.is_proc_macro())
+                    })
+                    .collect(),
+                dep_edge_map: &dep_edge_map,
+                feature_edge_map: &feature_edge_map,
+            };
+
+            let mut unit_features = BTreeMap::<usize, crate::UnitFeatures>::new();
+
+            for dev in [false, true] {
+                let states = crate::units::resolve(
+                    &units,
+                    root_seeds.keys().copied(),
+                    |root, _| root_seeds[&root].iter().copied(),
+                    true,
+                    dev,
+                    |_| {},
+                );
+
+                for ((pid, unit), state) in states {
+                    if !state.visited {
+                        continue;
+                    }
+
+                    let rnode = &nodes[pid];
+                    let features = Some(
+                        state
+                            .features
+                            .into_iter()
+                            .map(|feat| rnode.feature(feat).to_owned())
+                            .collect(),
+                    );
+
+                    let uf = unit_features.entry(pid).or_default();
+                    match (unit, dev) {
+                        (Unit::Target, false) => uf.target = features,
+                        (Unit::Host, false) => uf.host = features,
+                        (Unit::Target, true) => uf.dev = features,
+                        (Unit::Host, true) => uf.dev_host = features,
+                    }
+                }
+            }
+
+            unit_features
+        } else {
+            BTreeMap::new()
+        };
+
+        // The crates in the graph, which are only turned into the user's node
+        // type once every edge has been created, as the factories are given
+        // the final set of features enabled on each crate
+        struct KrateEntry {
+            id: Kid,
+            krate: crate::Package,
+            features: crate::EnabledFeatures,
+            unit_features: Option<Box<crate::UnitFeatures>>,
+            dep_mapping: Vec<Option<crate::NodeId>>,
+            depth: usize,
+        }
+
+        let mut krates = Vec::with_capacity(dep_edge_map.iter().flatten().count());
 
         let mut edge_count = 0;
 
+        // The crate node in the graph for each resolve node, and vice versa
+        let mut krate_ids = vec![None; nodes.len()];
+        let mut krate_nodes = Vec::new();
+
         // Preserve the ordering of the krates when inserting them into the graph
         // so that we can easily binary search for the crates based on their
         // package id with just the graph and no ancillary tables
         for (id, krate) in packages {
-            let Some(pn) = dep_edge_map.get(&id) else {
+            let Some((pid, pn)) = nodes
+                .binary_search_by(|n| n.id.cmp(&id))
+                .ok()
+                .and_then(|pid| Some((pid, dep_edge_map[pid].as_ref()?)))
+            else {
                 on_filter.filtered(krate);
                 continue;
             };
 
-            let rnode = get_rnode(&id);
+            let rnode = &nodes[pid];
 
             // If the crate is a root then the features it has enabled are
             // accurate, however if it is not a root then we need to manually
             // build up the list of enabled features as each edge is added
-            let features = if pn.is_root {
+            let features = if pn.is_root && !root_features.contains_key(&id) {
                 rnode.features.iter().cloned().collect()
             } else {
-                feature_edge_map[&id]
+                let Some(kf) = &feature_edge_map[pid] else {
+                    unreachable!("visited crate has no features");
+                };
+                let mut features: crate::EnabledFeatures = kf
                     .actual
                     .iter()
                     .map(|k| rnode.feature(*k).to_owned())
-                    .collect()
+                    .collect();
+
+                // Sub-features are normally added to the enabled features as
+                // the feature nodes are linked, but since they won't be, we
+                // need to add each crate's own sub-features here instead
+                if self.crates_only {
+                    let mut stack: Vec<_> = features.iter().cloned().collect();
+
+                    while let Some(feat) = stack.pop() {
+                        let Ok(i) = kf.graph.binary_search_by(|(name, _)| name.cmp(&feat)) else {
+                            continue;
+                        };
+
+                        for sf in &kf.graph[i].1 {
+                            if let FeatureEdgeName::Feature(name) = &sf.name
+                                && sf.krate == pid
+                                && features.insert(name.clone())
+                            {
+                                stack.push(name.clone());
+                            }
+                        }
+                    }
+                }
+
+                features
             };
 
             let dep_mapping = vec![None; krate.dependencies.len()];
+            let unit_features = self
+                .unit_features
+                .then(|| Box::new(unit_features.remove(&pid).unwrap_or_default()));
+
+            let depth = depths[pid].unwrap_or_default();
 
-            let krate = crate::Node::Krate {
+            krate_ids[pid] = Some(crate::NodeId::new(krates.len()));
+            krate_nodes.push(pid);
+            edge_count += pn.deps.len();
+
+            krates.push(KrateEntry {
                 id,
-                krate: N::from(krate),
+                krate,
                 features,
+                unit_features,
                 dep_mapping,
-            };
-
-            graph.add_node(krate);
-            edge_count += pn.deps.len();
+                depth,
+            });
         }
 
-        let krates_end = graph.node_count();
+        let krates_end = krates.len();
+        let crates_only = self.crates_only;
 
-        // Reserve space for each edge from a crate to another crate's feature(s)
-        graph.reserve_edges(edge_count);
+        // Without feature nodes there is nothing to link
+        if crates_only {
+            feature_edge_map.clear();
+            feature_edge_map.resize_with(nodes.len(), || None);
+        }
+
+        // The feature nodes of each crate, indexed by the crate's node index, so
+        // that we don't need to scan every feature node each time we link one
+        let mut feature_nodes = vec![BTreeMap::<String, crate::NodeId>::new(); krates_end];
 
-        let get = |graph: &petgraph::Graph<crate::Node<N>, E>,
-                   kid: &Kid,
+        let get = |feature_nodes: &[BTreeMap<String, crate::NodeId>],
+                   pid: usize,
                    feature: Option<&str>|
          -> Option<crate::NodeId> {
-            if let Some(feat) = feature {
-                graph.raw_nodes()[krates_end..]
-                    .iter()
-                    .enumerate()
-                    .find_map(|(i, n)| {
-                        if let crate::Node::Feature { krate_index, name } = &n.weight {
-                            if let crate::Node::Krate { id, .. } = &graph[*krate_index] {
-                                if id == kid && name == feat {
-                                    return Some(crate::NodeId::new(i + krates_end));
-                                }
-                            }
-                        }
+            let krate = krate_ids[pid]?;
 
-                        None
-                    })
+            if let Some(feat) = feature {
+                feature_nodes[krate.index()].get(feat).copied()
             } else {
-                graph.raw_nodes()[..krates_end]
-                    .iter()
-                    .enumerate()
-                    .find_map(|(i, n)| {
-                        if let crate::Node::Krate { id, .. } = &n.weight {
-                            if id == kid {
-                                return Some(crate::NodeId::new(i));
-                            }
-                        }
-
-                        None
-                    })
+                Some(krate)
             }
         };
 
+        // The feature nodes, which come after every crate node in the graph
+        let mut feature_list = Vec::<(crate::NodeId, String)>::new();
+
+        let add_feature = |feature_list: &mut Vec<(crate::NodeId, String)>,
+                           feature_nodes: &mut [BTreeMap<String, crate::NodeId>],
+                           krate_index: crate::NodeId,
+                           name: String|
+         -> crate::NodeId {
+            let feat_node = crate::NodeId::new(krates_end + feature_list.len());
+            feature_list.push((krate_index, name.clone()));
+
+            feature_nodes[krate_index.index()]
+                .entry(name)
+                .or_insert(feat_node);
+            feat_node
+        };
+
         // Now that we have all of the actual crate nodes, we can link all of the
         // features exposed by each crate
-        let (node_count, edge_count) =
-            feature_edge_map
-                .iter()
-                .fold((0usize, 0usize), |(nc, ec), (pid, feats)| {
-                    let nc = nc + feats.actual.len() * 2;
-                    let krate_index = nodes.binary_search_by(|n| n.id.cmp(pid)).unwrap();
-                    let rnode = &nodes[krate_index];
-
-                    let ec = ec
-                        + feats
-                            .graph
-                            .iter()
-                            .filter_map(|(name, sf)| {
-                                feats
-                                    .actual
-                                    .contains(&rnode.feature_index(name))
-                                    .then_some(sf.len())
-                            })
-                            .sum::<usize>();
+        let feature_edge_count = feature_edge_map
+            .iter()
+            .enumerate()
+            .filter_map(|(pid, feats)| Some((pid, feats.as_ref()?)))
+            .map(|(pid, feats)| {
+                let rnode = &nodes[pid];
+
+                feats
+                    .graph
+                    .iter()
+                    .filter_map(|(name, sf)| {
+                        rnode
+                            .feature_index(name)
+                            .is_some_and(|fi| feats.actual.contains(&fi))
+                            .then_some(sf.len())
+                    })
+                    .sum::<usize>()
+            })
+            .sum::<usize>();
+
+        // Each edge is created via the factory as soon as it is linked, so
+        // the graph only ever contains the user's edge type
+        let mut edges = Vec::with_capacity(edge_count + feature_edge_count);
+
+        let mut add_edge = |krates: &[KrateEntry],
+                            feature_list: &[(crate::NodeId, String)],
+                            edges: &mut Vec<(crate::NodeId, crate::NodeId, E)>,
+                            source: crate::NodeId,
+                            target: crate::NodeId,
+                            edge: Edge| {
+            let package = |nid: crate::NodeId| -> (&crate::Package, Option<&str>) {
+                match nid.index().checked_sub(krates_end) {
+                    None => (&krates[nid.index()].krate, None),
+                    Some(fi) => {
+                        let (krate_index, name) = &feature_list[fi];
+                        (&krates[krate_index.index()].krate, Some(name.as_str()))
+                    }
+                }
+            };
 
-                    (nc, ec)
-                });
+            let (source_krate, source_feature) = package(source);
+            let (target_krate, target_feature) = package(target);
+
+            let weight = edge_factory.create_edge(
+                EdgeContext {
+                    source: source_krate,
+                    source_feature,
+                    target: target_krate,
+                    target_feature,
+                },
+                edge,
+            );
 
-        graph.reserve_nodes(node_count);
-        graph.reserve_edges(edge_count);
+            edges.push((source, target, weight));
+        };
+
+        // Weak features only apply if the dependent actually ended up activating
+        // the dependency. Normally the features cargo resolved already tell us
+        // that, but not when the features of the roots were overridden, in
+        // which case we can only know once every crate is resolved
+        let activated: Option<BTreeSet<(usize, usize)>> = (!root_features.is_empty()).then(|| {
+            dep_edge_map
+                .iter()
+                .enumerate()
+                .filter_map(|(pid, pn)| Some((pid, pn.as_ref()?)))
+                .flat_map(|(pid, pn)| pn.deps.keys().map(move |did| (pid, *did)))
+                .collect()
+        });
 
         // Keep edges between crates ordered as well, though we don't depend on this
-        for srcind in 0..graph.node_count() {
+        for (srcind, pid) in krate_nodes.into_iter().enumerate() {
             let srcid = crate::NodeId::new(srcind);
-            let crate::Node::Krate { id: pid, .. } = &graph[srcid] else {
-                continue;
-            };
 
-            let Some(pn) = dep_edge_map.remove(pid) else {
+            let Some(pn) = dep_edge_map[pid].take() else {
                 continue;
             };
 
@@ -1652,113 +2659,116 @@ impl Builder {
             // actually be a target crate for the edge since crates can be pruned
             // due to target configuration
             for (dep_id, dep) in pn.deps {
-                let target_krate = if let Some(tk) = get(&graph, dep_id, None) {
-                    tk
-                } else {
+                let Some(target_krate) = krate_ids[dep_id] else {
                     continue;
                 };
 
-                let attach =
-                    |graph: &mut petgraph::Graph<crate::Node<N>, E>, feat: String, edge: Edge| {
-                        let feat_node = if let Some(feat_node) = get(graph, dep_id, Some(&feat)) {
-                            feat_node
-                        } else {
-                            graph.add_node(crate::Node::Feature {
-                                krate_index: target_krate,
-                                name: feat,
-                            })
-                        };
-
-                        graph.add_edge(srcid, feat_node, edge.into());
-                    };
+                let feature_node = |feature_list: &mut Vec<(crate::NodeId, String)>,
+                                    feature_nodes: &mut [BTreeMap<String, crate::NodeId>],
+                                    feat: &str|
+                 -> crate::NodeId {
+                    if let Some(feat_node) = get(feature_nodes, dep_id, Some(feat)) {
+                        feat_node
+                    } else {
+                        add_feature(feature_list, feature_nodes, target_krate, feat.to_owned())
+                    }
+                };
 
-                let rnode = get_rnode(dep_id);
+                let rnode = &nodes[dep_id];
 
                 // Add the features that were explicitly enabled by the specific
                 // normal/dev/build dependency
                 for edge in dep.edges {
-                    let attach_direct_edge = edge.features.is_empty();
+                    let attach_direct_edge = crates_only || edge.features.is_empty();
 
-                    for feat in edge.features {
-                        let feat = rnode.feature(feat);
-                        attach(
-                            &mut graph,
-                            feat.to_owned(),
+                    let edge_features = if crates_only {
+                        Vec::new()
+                    } else {
+                        edge.features
+                    };
+
+                    for feat in edge_features {
+                        let feat_node = feature_node(
+                            &mut feature_list,
+                            &mut feature_nodes,
+                            rnode.feature(feat),
+                        );
+                        add_edge(
+                            &krates,
+                            &feature_list,
+                            &mut edges,
+                            srcid,
+                            feat_node,
                             Edge::DepFeature {
                                 kind: edge.kind,
                                 cfg: edge.cfg.clone(),
+                                declaration: edge.declaration.clone(),
                             },
                         );
                     }
 
-                    if let crate::Node::Krate { dep_mapping, .. } = &mut graph[srcid] {
-                        dep_mapping[edge.dep_index] = Some(target_krate);
-                    }
+                    krates[srcind].dep_mapping[edge.declaration.index] = Some(target_krate);
 
                     if attach_direct_edge {
-                        graph.add_edge(
+                        add_edge(
+                            &krates,
+                            &feature_list,
+                            &mut edges,
                             srcid,
                             target_krate,
                             Edge::Dep {
                                 kind: edge.kind,
                                 cfg: edge.cfg,
-                            }
-                            .into(),
+                                declaration: edge.declaration,
+                            },
                         );
                     }
                 }
 
+                if crates_only {
+                    continue;
+                }
+
                 // Add the features that were toggled on via a parent crate feature
                 for feat in dep.features {
-                    let feat = rnode.feature(feat);
-                    attach(&mut graph, feat.to_owned(), Edge::Feature);
+                    let feat_node =
+                        feature_node(&mut feature_list, &mut feature_nodes, rnode.feature(feat));
+                    add_edge(
+                        &krates,
+                        &feature_list,
+                        &mut edges,
+                        srcid,
+                        feat_node,
+                        Edge::Feature,
+                    );
                 }
             }
         }
 
         // Now attach edges between all of features and their parent crate
-        for (pid, features) in feature_edge_map {
-            let Some((kind, mut feature_stack)) = get(&graph, pid, None).and_then(|kind| {
-                let crate::Node::Krate { features, .. } = &graph[kind] else {
-                    return None;
-                };
+        for (pid, krate_features) in feature_edge_map.into_iter().enumerate() {
+            let Some(krate_features) = krate_features else {
+                continue;
+            };
 
-                Some((kind, features.iter().cloned().collect::<Vec<_>>()))
-            }) else {
+            let Some(kind) = krate_ids[pid] else {
                 continue;
             };
 
-            let get_or_insert = |graph: &mut petgraph::Graph<crate::Node<N>, E>,
-                                 kid: &Kid,
+            let mut feature_stack: Vec<_> = krates[kind.index()].features.iter().cloned().collect();
+
+            let get_or_insert = |feature_list: &mut Vec<(crate::NodeId, String)>,
+                                 feature_nodes: &mut [BTreeMap<String, crate::NodeId>],
                                  feature: &str|
              -> crate::NodeId {
-                let node_id =
-                    graph.raw_nodes()[krates_end..]
-                        .iter()
-                        .enumerate()
-                        .find_map(|(i, n)| {
-                            if let crate::Node::Feature { krate_index, name } = &n.weight
-                                && let crate::Node::Krate { id, .. } = &graph[*krate_index]
-                                && id == kid
-                                && name == feature
-                            {
-                                Some(crate::NodeId::new(i + krates_end))
-                            } else {
-                                None
-                            }
-                        });
-
-                node_id.unwrap_or_else(|| {
-                    let krate_index = get(graph, kid, None).unwrap();
-
-                    graph.add_node(crate::Node::Feature {
-                        krate_index,
-                        name: feature.to_owned(),
-                    })
-                })
+                if let Some(node_id) = get(feature_nodes, pid, Some(feature)) {
+                    node_id
+                } else {
+                    add_feature(feature_list, feature_nodes, kind, feature.to_owned())
+                }
             };
 
-            let mut enabled = features.graph;
+            let mut enabled = krate_features.graph;
 
             // Since we can prune crates either by kind, target, or user specification,
             // the actual set of features might not be the same as those that
@@ -1774,13 +2784,30 @@ impl Builder {
                     continue;
                 };
 
-                let src_id = get_or_insert(&mut graph, pid, &feat);
+                let src_id = get_or_insert(&mut feature_list, &mut feature_nodes, &feat);
 
                 // Also add an edge from each feature to the crate node it belongs to
-                graph.add_edge(src_id, kind, E::from(crate::Edge::Feature));
+                add_edge(
+                    &krates,
+                    &feature_list,
+                    &mut edges,
+                    src_id,
+                    kind,
+                    Edge::Feature,
+                );
 
                 for sub_feat in sub_features {
-                    if sub_feat.kid != pid && get(&graph, sub_feat.kid, None).is_none() {
+                    if !sub_feat.unified
+                        || (sub_feat.krate != pid && krate_ids[sub_feat.krate].is_none())
+                    {
+                        continue;
+                    }
+
+                    if sub_feat.weak
+                        && activated
+                            .as_ref()
+                            .is_some_and(|activated| !activated.contains(&(pid, sub_feat.krate)))
+                    {
                         continue;
                     }
 
@@ -1791,43 +2818,140 @@ impl Builder {
                     };
 
                     let target_id = if let Some(target_id) =
-                        get(&graph, sub_feat.kid, feat_name.as_deref())
+                        get(&feature_nodes, sub_feat.krate, feat_name.as_deref())
                     {
                         target_id
                     } else {
-                        let feat_name = feat_name.unwrap_or_else(|| sub_feat.kid.name().to_owned());
-
-                        let target_id = graph.add_node(crate::Node::Feature {
-                            krate_index: kind,
-                            name: feat_name.clone(),
-                        });
+                        let feat_name =
+                            feat_name.unwrap_or_else(|| nodes[sub_feat.krate].id.name().to_owned());
+
+                        let target_id = add_feature(
+                            &mut feature_list,
+                            &mut feature_nodes,
+                            kind,
+                            feat_name.clone(),
+                        );
 
                         // Ensure that all of the subfeatures enabled by the parent feature are added to the
                         // flat list of enabled features for the crate
-                        if let crate::Node::Krate { features, .. } = &mut graph[kind] {
-                            if !features.contains(&feat_name) {
-                                features.insert(feat_name.clone());
-                                feature_stack.push(feat_name);
-                            }
+                        let features = &mut krates[kind.index()].features;
+                        if !features.contains(&feat_name) {
+                            features.insert(feat_name.clone());
+                            feature_stack.push(feat_name);
                         }
 
                         target_id
                     };
 
-                    graph.add_edge(src_id, target_id, E::from(crate::Edge::Feature));
+                    add_edge(
+                        &krates,
+                        &feature_list,
+                        &mut edges,
+                        src_id,
+                        target_id,
+                        Edge::Feature,
+                    );
                 }
             }
         }
 
+        if let Some(on_diagnostic) = &mut on_diagnostic {
+            for diag in diagnostics.found {
+                on_diagnostic(diag);
+            }
+        }
+
+        // Now that every crate has its final set of features, the crates can
+        // be moved into the graph as the user's node type
+        let mut graph = petgraph::Graph::<crate::Node<N>, E>::with_capacity(
+            krates_end + feature_list.len(),
+            edges.len(),
+        );
+
+        for KrateEntry {
+            id,
+            krate,
+            features,
+            unit_features,
+            dep_mapping,
+            depth,
+        } in krates
+        {
+            let krate = node_factory.create_node(
+                NodeContext {
+                    id: &id,
+                    features: &features,
+                    unit_features: unit_features.as_deref(),
+                    is_workspace_member: workspace_members.binary_search(&id).is_ok(),
+                    depth,
+                },
+                krate,
+            );
+
+            graph.add_node(crate::Node::Krate {
+                id,
+                krate,
+                features,
+                unit_features,
+                dep_mapping,
+                depth,
+            });
+        }
+
+        for (krate_index, name) in feature_list {
+            graph.add_node(crate::Node::Feature { krate_index, name });
+        }
+
+        for (source, target, weight) in edges {
+            graph.add_edge(source, target, weight);
+        }
+
+        let lookups = crate::Lookups::new(&graph, krates_end);
+
+        let no_default_roots = root_features
+            .into_iter()
+            .filter_map(|(kid, (_, default))| (!default).then_some(kid))
+            .collect();
+
         Ok(Krates {
             graph,
             workspace_members,
             workspace_root: md.workspace_root,
+            no_default_roots,
             krates_end,
+            lookups,
         })
     }
 }
 
+/// The diagnostics encountered while building the graph, in the order they
+/// were found, which are only collected if there is a callback for them
+struct Diagnostics {
+    collect: bool,
+    seen: std::collections::HashSet<Diagnostic>,
+    found: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    fn new(collect: bool) -> Self {
+        Self {
+            collect,
+            seen: Default::default(),
+            found: Vec::new(),
+        }
+    }
+
+    /// Adds the diagnostic, unless it has already been added, as the same
+    /// crate can be visited multiple times while resolving features
+    #[inline]
+    fn push(&mut self, diag: Diagnostic) {
+        if self.collect && !self.seen.contains(&diag) {
+            self.seen.insert(diag.clone());
+            self.found.push(diag);
+        }
+    }
+}
+
 #[inline]
 fn compare_paths(dpath: &camino::Utf8Path, src: &str) -> bool {
     let Some(rpath) = src.strip_prefix("path+file://") else {
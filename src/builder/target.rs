@@ -0,0 +1,264 @@
+use super::{Cfg, Target};
+use crate::Error;
+use cfg_expr::targets::{
+    Abi, Arch, Endian, Env, Families, Family, HasAtomic, HasAtomics, Os, Panic, TargetInfo, Triple,
+    Vendor,
+};
+
+/// A target that is not known to `cfg-expr`, whose configuration was instead
+/// supplied by the user, eg. via [`Target::from_rustc_cfg`] or
+/// [`Target::from_target_spec`]
+#[derive(Clone, Debug)]
+pub struct CustomTarget {
+    /// The target information used to evaluate target predicates, eg.
+    /// `target_os`, `target_arch` etc
+    pub info: TargetInfo,
+    /// The `target_feature`s enabled for the target
+    pub features: Vec<String>,
+    /// Any other cfgs set for the target, eg. `debug_assertions`
+    pub cfgs: Vec<Cfg>,
+}
+
+#[inline]
+fn invalid(triple: &str, reason: impl std::fmt::Display) -> Error {
+    Error::InvalidTarget(format!("'{triple}': {reason}"))
+}
+
+/// Empty values, and `os = "none"` mean the value is not present, which is
+/// how `cfg-expr` represents them for its builtin targets
+#[inline]
+fn optional<T>(val: Option<&str>, none: &str, f: impl FnOnce(String) -> T) -> Option<T> {
+    val.filter(|v| !v.is_empty() && *v != none)
+        .map(|v| f(v.to_owned()))
+}
+
+fn parse_atomic(triple: &str, val: &str) -> Result<HasAtomic, Error> {
+    val.parse()
+        .map_err(|_err| invalid(triple, format_args!("invalid atomic width '{val}'")))
+}
+
+impl Target {
+    /// Creates a target from the output of `rustc --print cfg --target <triple>`,
+    /// which allows `cfg()` expressions to be evaluated for targets that are
+    /// not builtin to `cfg-expr`, eg. tier 3 or custom targets.
+    ///
+    /// In addition to the target predicates, any `target_feature`s, as well
+    /// as any other cfgs present in the output such as `debug_assertions` or
+    /// ones set via `--cfg` are also used when evaluating expressions.
+    ///
+    /// ```
+    /// # use krates::Target;
+    /// let output = r#"panic="abort"
+    /// target_arch="arm"
+    /// target_endian="little"
+    /// target_feature="v7"
+    /// target_has_atomic="32"
+    /// target_has_atomic="ptr"
+    /// target_os="none"
+    /// target_pointer_width="32"
+    /// target_vendor="unknown"
+    /// "#;
+    ///
+    /// Target::from_rustc_cfg("thumbv7em-custom-none-eabi", output).unwrap();
+    /// ```
+    pub fn from_rustc_cfg(triple: &str, output: &str) -> Result<Self, Error> {
+        let mut os = None;
+        let mut abi = None;
+        let mut arch = None;
+        let mut env = None;
+        let mut vendor = None;
+        let mut families = Vec::new();
+        let mut pointer_width = None;
+        let mut endian = None;
+        let mut has_atomics = Vec::new();
+        let mut panic = None;
+        let mut features = Vec::new();
+        let mut cfgs = Vec::new();
+
+        for line in output.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let cfg: Cfg = line
+                .parse()
+                .map_err(|err| invalid(triple, format_args!("'{line}': {err}")))?;
+
+            let Cfg::KeyValue { key, value } = &cfg else {
+                cfgs.push(cfg);
+                continue;
+            };
+
+            match key.as_str() {
+                "target_os" => os = Some(value.clone()),
+                "target_abi" => abi = Some(value.clone()),
+                "target_arch" => arch = Some(value.clone()),
+                "target_env" => env = Some(value.clone()),
+                "target_vendor" => vendor = Some(value.clone()),
+                "target_family" => families.push(Family::new(value.clone())),
+                "target_pointer_width" => {
+                    pointer_width = Some(value.parse::<u8>().map_err(|_err| {
+                        invalid(triple, format_args!("invalid pointer width '{value}'"))
+                    })?);
+                }
+                "target_endian" => {
+                    endian = Some(value.parse::<Endian>().map_err(|_err| {
+                        invalid(triple, format_args!("invalid endianness '{value}'"))
+                    })?);
+                }
+                "target_has_atomic" => has_atomics.push(parse_atomic(triple, value)?),
+                "panic" => panic = Some(value.clone()),
+                "target_feature" => features.push(value.clone()),
+                _ => cfgs.push(cfg),
+            }
+        }
+
+        let info = TargetInfo {
+            triple: Triple::new(triple.to_owned()),
+            os: optional(os.as_deref(), "none", Os::new),
+            abi: optional(abi.as_deref(), "", Abi::new),
+            arch: Arch::new(arch.ok_or_else(|| invalid(triple, "target_arch was not specified"))?),
+            env: optional(env.as_deref(), "", Env::new),
+            vendor: optional(vendor.as_deref(), "", Vendor::new),
+            families: Families::new(families),
+            pointer_width: pointer_width
+                .ok_or_else(|| invalid(triple, "target_pointer_width was not specified"))?,
+            endian: endian.ok_or_else(|| invalid(triple, "target_endian was not specified"))?,
+            has_atomics: HasAtomics::new(has_atomics),
+            panic: Panic::new(panic.unwrap_or_else(|| "unwind".to_owned())),
+        };
+
+        Ok(Self::Custom(Box::new(CustomTarget {
+            info,
+            features,
+            cfgs,
+        })))
+    }
+
+    /// Creates a target from a [target specification](https://doc.rust-lang.org/rustc/targets/custom.html)
+    /// JSON file, as used for custom targets, eg. `--target my-target.json`.
+    ///
+    /// The `triple` is the name of the target, which is the file stem of the
+    /// JSON file, and is what cargo uses when matching `[target.<triple>]`
+    /// dependencies. Features enabled via the `features` key are treated as
+    /// `target_feature`s.
+    ///
+    /// ```
+    /// # use krates::Target;
+    /// let spec = r#"{
+    ///     "arch": "arm",
+    ///     "data-layout": "e-m:e-p:32:32-Fi8-i64:64-v128:64:128-a:0:32-n32-S64",
+    ///     "llvm-target": "thumbv7em-none-eabi",
+    ///     "max-atomic-width": 32,
+    ///     "panic-strategy": "abort",
+    ///     "target-pointer-width": "32",
+    ///     "features": "+v7,+soft-float,-neon"
+    /// }"#;
+    ///
+    /// Target::from_target_spec("my-target", spec).unwrap();
+    /// ```
+    pub fn from_target_spec(triple: &str, json: &str) -> Result<Self, Error> {
+        let spec: serde_json::Value = serde_json::from_str(json)
+            .map_err(|err| invalid(triple, format_args!("failed to parse target spec: {err}")))?;
+
+        let Some(spec) = spec.as_object() else {
+            return Err(invalid(triple, "target spec is not an object"));
+        };
+
+        let get_str = |key: &str| -> Result<Option<&str>, Error> {
+            match spec.get(key) {
+                None => Ok(None),
+                Some(serde_json::Value::String(s)) => Ok(Some(s.as_str())),
+                Some(_) => Err(invalid(triple, format_args!("'{key}' is not a string"))),
+            }
+        };
+
+        // Older versions of rustc used strings for integers
+        let get_int = |key: &str| -> Result<Option<u64>, Error> {
+            match spec.get(key) {
+                None => Ok(None),
+                Some(serde_json::Value::Number(n)) => n
+                    .as_u64()
+                    .map(Some)
+                    .ok_or_else(|| invalid(triple, format_args!("'{key}' is not an integer"))),
+                Some(serde_json::Value::String(s)) => s
+                    .parse()
+                    .map(Some)
+                    .map_err(|_err| invalid(triple, format_args!("'{key}' is not an integer"))),
+                Some(_) => Err(invalid(triple, format_args!("'{key}' is not an integer"))),
+            }
+        };
+
+        let arch = get_str("arch")?.ok_or_else(|| invalid(triple, "'arch' was not specified"))?;
+
+        let pointer_width = get_int("target-pointer-width")?
+            .ok_or_else(|| invalid(triple, "'target-pointer-width' was not specified"))?;
+        let pointer_width = u8::try_from(pointer_width).map_err(|_err| {
+            invalid(
+                triple,
+                format_args!("invalid pointer width '{pointer_width}'"),
+            )
+        })?;
+
+        let endian = match get_str("target-endian")? {
+            Some(endian) => endian
+                .parse::<Endian>()
+                .map_err(|_err| invalid(triple, format_args!("invalid endianness '{endian}'")))?,
+            None => Endian::little,
+        };
+
+        let families = match spec.get("target-family") {
+            None => Vec::new(),
+            Some(serde_json::Value::String(fam)) => vec![Family::new(fam.clone())],
+            Some(serde_json::Value::Array(fams)) => fams
+                .iter()
+                .map(|fam| {
+                    fam.as_str()
+                        .map(|fam| Family::new(fam.to_owned()))
+                        .ok_or_else(|| invalid(triple, "'target-family' is not a list of strings"))
+                })
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err(invalid(triple, "'target-family' is not a list of strings")),
+        };
+
+        // rustc defaults the max atomic width to the pointer width, and only
+        // supports atomics of 8 bits or more
+        let max_atomic = get_int("max-atomic-width")?.unwrap_or(pointer_width.into());
+        let min_atomic = get_int("min-atomic-width")?.unwrap_or(8);
+        let mut has_atomics: Vec<_> = [8, 16, 32, 64, 128]
+            .into_iter()
+            .filter(|width| (min_atomic..=max_atomic).contains(width))
+            .map(|width| HasAtomic::IntegerSize(width as u16))
+            .collect();
+
+        if max_atomic >= u64::from(pointer_width) {
+            has_atomics.push(HasAtomic::Pointer);
+        }
+
+        let features = get_str("features")?
+            .map(|features| {
+                features
+                    .split(',')
+                    .filter_map(|feat| feat.trim().strip_prefix('+'))
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let info = TargetInfo {
+            triple: Triple::new(triple.to_owned()),
+            os: optional(get_str("os")?, "none", Os::new),
+            abi: optional(get_str("abi")?, "", Abi::new),
+            arch: Arch::new(arch.to_owned()),
+            env: optional(get_str("env")?, "", Env::new),
+            vendor: optional(get_str("vendor")?, "", Vendor::new),
+            families: Families::new(families),
+            pointer_width,
+            endian,
+            has_atomics: HasAtomics::new(has_atomics),
+            panic: Panic::new(get_str("panic-strategy")?.unwrap_or("unwind").to_owned()),
+        };
+
+        Ok(Self::Custom(Box::new(CustomTarget {
+            info,
+            features,
+            cfgs: Vec::new(),
+        })))
+    }
+}
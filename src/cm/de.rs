@@ -381,7 +381,9 @@ map!(Target, map, {
             "name" => name = Some(tri!(map.next_value())),
             "kind" => kind = Some(tri!(map.next_value())),
             "crate_types" => crate_types = tri!(map.next_value()),
-            "required-features" => required_features = tri!(map.next_value()),
+            "required-features" | "required_features" => {
+                required_features = tri!(map.next_value());
+            }
             "src_path" => src_path = Some(tri!(map.next_value())),
             "edition" => edition = tri!(map.next_value()),
             "doctest" => doctest = tri!(map.next_value()),
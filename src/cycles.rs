@@ -0,0 +1,152 @@
+use crate::{DepKind, Edge, EdgeId, Krates, NodeId};
+use petgraph::visit::EdgeRef;
+use std::collections::BTreeMap;
+
+/// A group of crates that all transitively depend on each other, ie. a
+/// strongly connected component of the crate nodes in the graph
+pub struct Cycle {
+    /// The crates in the cycle, sorted by their node id
+    pub krates: Vec<NodeId>,
+    /// Every dependency between two crates in the cycle, sorted by the
+    /// dependent and then the dependency
+    pub dependencies: Vec<CycleDependency>,
+}
+
+/// A dependency between two crates in a [`Cycle`]
+pub struct CycleDependency {
+    /// The crate with the dependency
+    pub dependent: NodeId,
+    /// The crate being depended upon
+    pub dependency: NodeId,
+    /// The first edge that links the dependent with the dependency, or one of
+    /// its features
+    pub edge_id: EdgeId,
+    /// Every kind the dependency is declared as, in the order normal, build, dev
+    pub kinds: Vec<DepKind>,
+}
+
+impl Cycle {
+    /// True if at least one dependency in the cycle is only a dev-dependency,
+    /// which is the only way cargo allows cycles
+    pub fn is_dev_cycle(&self) -> bool {
+        self.dependencies
+            .iter()
+            .any(|dep| dep.kinds.iter().all(|kind| *kind == DepKind::Dev))
+    }
+}
+
+/// The edges from a crate to each of its dependencies, along with their kind
+pub(crate) type CrateDependencies = Vec<BTreeMap<NodeId, Vec<(EdgeId, DepKind)>>>;
+
+impl<N> Krates<N, Edge> {
+    /// Gets the dependencies of every crate, ignoring the feature nodes
+    pub(crate) fn crate_dependencies(&self) -> CrateDependencies {
+        let mut deps = vec![BTreeMap::<_, Vec<_>>::new(); self.krates_end];
+
+        for edge in self.graph.edge_references() {
+            let (Edge::Dep { kind, .. } | Edge::DepFeature { kind, .. }) = edge.weight() else {
+                continue;
+            };
+
+            let dependent = edge.source();
+            let dependency = self.owner(edge.target());
+
+            if dependent.index() >= self.krates_end || dependent == dependency {
+                continue;
+            }
+
+            deps[dependent.index()]
+                .entry(dependency)
+                .or_default()
+                .push((edge.id(), *kind));
+        }
+
+        deps
+    }
+
+    /// Gets every cycle between crates, eg. a crate that has a dev-dependency
+    /// on a crate that depends on it, which cargo allows since dev-dependencies
+    /// are not needed to build the crate itself. Cycles are sorted by their
+    /// lowest node id.
+    ///
+    /// Traversals provided by [`Krates`] handle cycles, but custom traversals
+    /// of [`Self::graph`] need to track the nodes they have already visited.
+    ///
+    /// ```no_run
+    /// use krates::Krates;
+    ///
+    /// fn print_cycles(krates: &Krates) {
+    ///     for cycle in krates.cycles() {
+    ///         for dep in cycle.dependencies {
+    ///             println!(
+    ///                 "{} -> {} {:?}",
+    ///                 krates[dep.dependent].name,
+    ///                 krates[dep.dependency].name,
+    ///                 dep.kinds,
+    ///             );
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn cycles(&self) -> Vec<Cycle> {
+        let deps = self.crate_dependencies();
+
+        let mut cycles: Vec<_> = Self::crate_cycles(&deps)
+            .into_iter()
+            .map(|krates| {
+                let mut dependencies = Vec::new();
+
+                for dependent in &krates {
+                    for (dependency, edges) in &deps[dependent.index()] {
+                        if krates.binary_search(dependency).is_err() {
+                            continue;
+                        }
+
+                        let kinds = [DepKind::Normal, DepKind::Build, DepKind::Dev]
+                            .into_iter()
+                            .filter(|kind| edges.iter().any(|(_, k)| k == kind))
+                            .collect();
+
+                        dependencies.push(CycleDependency {
+                            dependent: *dependent,
+                            dependency: *dependency,
+                            edge_id: edges[0].0,
+                            kinds,
+                        });
+                    }
+                }
+
+                Cycle {
+                    krates,
+                    dependencies,
+                }
+            })
+            .collect();
+
+        cycles.sort_by_key(|cycle| cycle.krates[0]);
+        cycles
+    }
+
+    /// Gets the strongly connected components of the crates with more than
+    /// one crate, each sorted by node id
+    pub(crate) fn crate_cycles(deps: &CrateDependencies) -> Vec<Vec<NodeId>> {
+        let mut graph = petgraph::Graph::<(), ()>::with_capacity(deps.len(), 0);
+        for _ in 0..deps.len() {
+            graph.add_node(());
+        }
+        for (dependent, dependencies) in deps.iter().enumerate() {
+            for dependency in dependencies.keys() {
+                graph.add_edge(NodeId::new(dependent), *dependency, ());
+            }
+        }
+
+        petgraph::algo::tarjan_scc(&graph)
+            .into_iter()
+            .filter(|scc| scc.len() > 1)
+            .map(|mut scc| {
+                scc.sort();
+                scc
+            })
+            .collect()
+    }
+}
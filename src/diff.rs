@@ -0,0 +1,409 @@
+use crate::{DepKind, Edge, Kid, KrateDetails, Krates, Node, NodeId, duplicates::compatible_part};
+use petgraph::{Direction, visit::EdgeRef};
+use std::collections::BTreeMap;
+
+/// The differences between two crate graphs, see [`diff`]
+pub struct Diff<'krates> {
+    /// The crates that are only in the new graph, and aren't a different
+    /// version or source of a crate in the old graph
+    pub added: Vec<&'krates Kid>,
+    /// The crates that are only in the old graph, and aren't a different
+    /// version or source of a crate in the new graph
+    pub removed: Vec<&'krates Kid>,
+    /// The crates whose version or source changed
+    pub changed: Vec<KrateChange<'krates>>,
+    /// The crates in both graphs whose enabled features changed
+    pub features: Vec<FeatureChange<'krates>>,
+    /// The dependencies that were added or removed
+    pub dependencies: Vec<DependencyChange<'krates>>,
+}
+
+/// How the version of a [`KrateChange`] changed
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VersionChange {
+    /// The new version is higher
+    Upgraded,
+    /// The new version is lower
+    Downgraded,
+    /// The version is the same, only the source changed
+    Unchanged,
+}
+
+/// A crate whose version or source is different in the new graph
+pub struct KrateChange<'krates> {
+    /// The name of the crate
+    pub name: &'krates str,
+    /// The crate in the old graph
+    pub old: &'krates Kid,
+    /// The crate in the new graph
+    pub new: &'krates Kid,
+    /// How the version changed
+    pub version: VersionChange,
+    /// True if the crate comes from a different source, eg. a git repository
+    /// instead of a registry
+    pub source_changed: bool,
+}
+
+/// The enabled features of a crate that changed between graphs
+pub struct FeatureChange<'krates> {
+    /// The crate in the old graph
+    pub old: &'krates Kid,
+    /// The crate in the new graph
+    pub new: &'krates Kid,
+    /// The features that are only enabled in the new graph
+    pub added: Vec<&'krates str>,
+    /// The features that are only enabled in the old graph
+    pub removed: Vec<&'krates str>,
+}
+
+/// Whether a [`DependencyChange`] was added or removed
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    /// The dependency is only in the new graph
+    Added,
+    /// The dependency is only in the old graph
+    Removed,
+}
+
+/// A dependency between two crates that is only in one of the graphs
+pub struct DependencyChange<'krates> {
+    /// Whether the dependency was added or removed
+    pub change: Change,
+    /// The crate with the dependency, in the graph the dependency is in
+    pub dependent: &'krates Kid,
+    /// The crate being depended upon, in the graph the dependency is in
+    pub dependency: &'krates Kid,
+    /// The kind of the dependency
+    pub kind: DepKind,
+    /// The `cfg()` or target the dependency is limited to, if any
+    pub cfg: Option<&'krates str>,
+}
+
+/// A dependency is identified by the name of the crate it is on rather than
+/// its id, so that a crate changing version is not also reported as a
+/// removed and an added dependency. The rename is included, as depending on
+/// more than one version of a crate requires renaming them
+type DependencyKey<'krates> = (&'krates str, Option<&'krates str>, u8, Option<&'krates str>);
+
+/// Whether a crate only in the old graph is a match for a crate only in the new graph
+type MatchRule<N> = fn(&Kid, &N, &Kid, &N) -> bool;
+
+struct Dependency<'krates> {
+    dependency: &'krates Kid,
+    kind: DepKind,
+    cfg: Option<&'krates str>,
+}
+
+#[inline]
+fn kind_order(kind: DepKind) -> u8 {
+    match kind {
+        DepKind::Normal => 0,
+        DepKind::Build => 1,
+        DepKind::Dev => 2,
+    }
+}
+
+impl<N, E> Krates<N, E> {
+    /// Gets the crate nodes along with their id
+    #[inline]
+    fn crate_nodes(&self) -> impl Iterator<Item = (NodeId, &Kid, &N)> {
+        self.graph.raw_nodes()[..self.krates_end]
+            .iter()
+            .enumerate()
+            .filter_map(|(index, node)| match &node.weight {
+                Node::Krate { id, krate, .. } => Some((NodeId::new(index), id, krate)),
+                Node::Feature { .. } => None,
+            })
+    }
+}
+
+impl<N> Krates<N, Edge> {
+    /// Gets the dependencies of a crate, ignoring which features they are
+    /// attached to
+    fn diff_dependencies(&self, nid: NodeId) -> BTreeMap<DependencyKey<'_>, Dependency<'_>> {
+        let mut dependencies = BTreeMap::new();
+
+        for edge in self.graph.edges_directed(nid, Direction::Outgoing) {
+            let (Edge::Dep {
+                kind,
+                cfg,
+                declaration,
+            }
+            | Edge::DepFeature {
+                kind,
+                cfg,
+                declaration,
+            }) = edge.weight()
+            else {
+                continue;
+            };
+
+            let Node::Krate { id, .. } = &self.graph[self.owner(edge.target())] else {
+                continue;
+            };
+
+            let cfg = cfg.as_deref();
+            dependencies
+                .entry((
+                    id.name(),
+                    declaration.rename.as_deref(),
+                    kind_order(*kind),
+                    cfg,
+                ))
+                .or_insert(Dependency {
+                    dependency: id,
+                    kind: *kind,
+                    cfg,
+                });
+        }
+
+        dependencies
+    }
+}
+
+/// Gets the differences between two crate graphs, eg. before and after
+/// updating dependencies.
+///
+/// Crates are matched by their id. A crate that is only in one of the graphs
+/// is reported as changed, rather than added or removed, if the other graph
+/// has a crate with the same name that is from the same source, from the same
+/// kind of source with a semver compatible version, eg. a different git
+/// revision, or with the same version from any source. If there is only one
+/// unmatched crate with the name in each graph, they are always matched.
+///
+/// Dependencies are compared by the name of the crate they are on, their
+/// rename, kind, and cfg, so that a dependency on a crate that changed version
+/// is not reported. The dependencies of added and removed crates are also reported.
+///
+/// ```no_run
+/// use krates::{Krates, VersionChange};
+///
+/// fn print_diff(old: &Krates, new: &Krates) {
+///     let diff = krates::diff(old, new);
+///
+///     for added in diff.added {
+///         println!("+ {added}");
+///     }
+///     for removed in diff.removed {
+///         println!("- {removed}");
+///     }
+///     for change in diff.changed {
+///         let verb = match change.version {
+///             VersionChange::Upgraded => "upgraded",
+///             VersionChange::Downgraded => "downgraded",
+///             VersionChange::Unchanged => "changed source",
+///         };
+///         println!("{} {verb}: {} -> {}", change.name, change.old, change.new);
+///     }
+/// }
+/// ```
+pub fn diff<'krates, N>(
+    old: &'krates Krates<N, Edge>,
+    new: &'krates Krates<N, Edge>,
+) -> Diff<'krates>
+where
+    N: KrateDetails,
+{
+    let mut diff = Diff {
+        added: Vec::new(),
+        removed: Vec::new(),
+        changed: Vec::new(),
+        features: Vec::new(),
+        dependencies: Vec::new(),
+    };
+
+    // The crates only in one of the graphs, grouped by name
+    let mut unmatched = BTreeMap::<&str, (Vec<_>, Vec<_>)>::new();
+    // The pairs of crates in both graphs
+    let mut pairs = Vec::new();
+
+    for (old_nid, id, krate) in old.crate_nodes() {
+        match new.nid_for_kid(id) {
+            Some(new_nid) => pairs.push((old_nid, new_nid)),
+            None => unmatched
+                .entry(id.name())
+                .or_default()
+                .0
+                .push((old_nid, id, krate)),
+        }
+    }
+
+    for (new_nid, id, krate) in new.crate_nodes() {
+        if old.nid_for_kid(id).is_none() {
+            unmatched
+                .entry(id.name())
+                .or_default()
+                .1
+                .push((new_nid, id, krate));
+        }
+    }
+
+    for (name, (mut olds, mut news)) in unmatched {
+        let mut matched = Vec::new();
+
+        // Prefer the same source with a compatible version, then the same
+        // source, then the same kind of source with a compatible version, eg.
+        // a different git revision, then the same version from any source
+        let rules: [MatchRule<N>; 4] = [
+            |ok, okrate, nk, nkrate| {
+                ok.source() == nk.source()
+                    && compatible_part(okrate.version()) == compatible_part(nkrate.version())
+            },
+            |ok, _, nk, _| ok.source() == nk.source(),
+            |ok, okrate, nk, nkrate| {
+                ok.source_kind() == nk.source_kind()
+                    && compatible_part(okrate.version()) == compatible_part(nkrate.version())
+            },
+            |_, okrate, _, nkrate| okrate.version() == nkrate.version(),
+        ];
+
+        for rule in rules {
+            let mut i = 0;
+            while i < olds.len() {
+                let (_, ok, okrate) = olds[i];
+                if let Some(j) = news
+                    .iter()
+                    .position(|(_, nk, nkrate)| rule(ok, okrate, nk, nkrate))
+                {
+                    matched.push((olds.remove(i), news.remove(j)));
+                } else {
+                    i += 1;
+                }
+            }
+        }
+
+        if olds.len() == 1 && news.len() == 1 {
+            matched.push((olds.remove(0), news.remove(0)));
+        }
+
+        for ((old_nid, ok, okrate), (new_nid, nk, nkrate)) in matched {
+            diff.changed.push(KrateChange {
+                name,
+                old: ok,
+                new: nk,
+                version: match okrate.version().cmp(nkrate.version()) {
+                    std::cmp::Ordering::Less => VersionChange::Upgraded,
+                    std::cmp::Ordering::Greater => VersionChange::Downgraded,
+                    std::cmp::Ordering::Equal => VersionChange::Unchanged,
+                },
+                source_changed: ok.source() != nk.source(),
+            });
+            pairs.push((old_nid, new_nid));
+        }
+
+        diff.removed.extend(olds.into_iter().map(|(nid, id, _)| {
+            diff_all_dependencies(&mut diff.dependencies, old, nid, id, Change::Removed);
+            id
+        }));
+        diff.added.extend(news.into_iter().map(|(nid, id, _)| {
+            diff_all_dependencies(&mut diff.dependencies, new, nid, id, Change::Added);
+            id
+        }));
+    }
+
+    for (old_nid, new_nid) in pairs {
+        let Node::Krate {
+            id: ok,
+            features: old_features,
+            ..
+        } = &old.graph[old_nid]
+        else {
+            unreachable!();
+        };
+        let Node::Krate {
+            id: nk,
+            features: new_features,
+            ..
+        } = &new.graph[new_nid]
+        else {
+            unreachable!();
+        };
+
+        let added: Vec<_> = new_features
+            .difference(old_features)
+            .map(|f| f.as_str())
+            .collect();
+        let removed: Vec<_> = old_features
+            .difference(new_features)
+            .map(|f| f.as_str())
+            .collect();
+
+        if !added.is_empty() || !removed.is_empty() {
+            diff.features.push(FeatureChange {
+                old: ok,
+                new: nk,
+                added,
+                removed,
+            });
+        }
+
+        let old_deps = old.diff_dependencies(old_nid);
+        let mut new_deps = new.diff_dependencies(new_nid);
+
+        for (key, dep) in old_deps {
+            if new_deps.remove(&key).is_none() {
+                diff.dependencies.push(DependencyChange {
+                    change: Change::Removed,
+                    dependent: ok,
+                    dependency: dep.dependency,
+                    kind: dep.kind,
+                    cfg: dep.cfg,
+                });
+            }
+        }
+
+        diff.dependencies
+            .extend(new_deps.into_values().map(|dep| DependencyChange {
+                change: Change::Added,
+                dependent: nk,
+                dependency: dep.dependency,
+                kind: dep.kind,
+                cfg: dep.cfg,
+            }));
+    }
+
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed.sort_by(|a, b| a.old.cmp(b.old));
+    diff.features.sort_by(|a, b| a.new.cmp(b.new));
+    diff.dependencies.sort_by(|a, b| {
+        (
+            a.dependent,
+            a.dependency,
+            kind_order(a.kind),
+            a.cfg,
+            a.change,
+        )
+            .cmp(&(
+                b.dependent,
+                b.dependency,
+                kind_order(b.kind),
+                b.cfg,
+                b.change,
+            ))
+    });
+
+    diff
+}
+
+/// Adds every dependency of a crate that is only in one of the graphs
+fn diff_all_dependencies<'krates, N>(
+    dependencies: &mut Vec<DependencyChange<'krates>>,
+    krates: &'krates Krates<N, Edge>,
+    nid: NodeId,
+    id: &'krates Kid,
+    change: Change,
+) {
+    dependencies.extend(
+        krates
+            .diff_dependencies(nid)
+            .into_values()
+            .map(|dep| DependencyChange {
+                change,
+                dependent: id,
+                dependency: dep.dependency,
+                kind: dep.kind,
+                cfg: dep.cfg,
+            }),
+    );
+}
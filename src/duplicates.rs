@@ -0,0 +1,209 @@
+use crate::{Edge, KrateDetails, Krates, Node, NodeId, SourceKind};
+use petgraph::{Direction, visit::EdgeRef};
+use std::collections::BTreeMap;
+
+/// How the versions of a duplicated crate relate to each other
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Compatibility {
+    /// Every version is semver compatible with the others, eg. `1.2.0` and
+    /// `1.4.1`, so they could be unified by relaxing version requirements or
+    /// updating the lockfile
+    Compatible,
+    /// Every version has the same major version, but at least one is semver
+    /// incompatible with another, eg. `0.1.0` and `0.2.0`
+    SameMajor,
+    /// At least one version has a different major version, eg. `1.0.0` and `2.0.0`
+    DifferentMajor,
+}
+
+/// A crate with more than one version in the graph
+pub struct Duplicate<'krates, N> {
+    /// The name of the crate
+    pub name: &'krates str,
+    /// The kind of source every version comes from
+    pub source_kind: SourceKind,
+    /// How the versions relate to each other
+    pub compatibility: Compatibility,
+    /// Each version of the crate, from lowest to highest
+    pub versions: Vec<DuplicateVersion<'krates, N>>,
+}
+
+/// A single version of a [`Duplicate`]
+pub struct DuplicateVersion<'krates, N> {
+    /// The crate's node id
+    pub node_id: NodeId,
+    /// The crate in the node
+    pub krate: &'krates N,
+    /// The crates that depend on this version
+    pub dependents: Vec<VersionDependent<'krates, N>>,
+}
+
+/// A crate that depends on a specific version of a [`Duplicate`]
+pub struct VersionDependent<'krates, N> {
+    /// The dependent's node id
+    pub node_id: NodeId,
+    /// The crate in the node
+    pub krate: &'krates N,
+    /// The version requirements the dependent declared, which are the ones
+    /// that would need to change to use a different version
+    pub requirements: Vec<&'krates semver::VersionReq>,
+}
+
+/// Gets the part of the version that must match for versions to be semver
+/// compatible, the same as cargo uses when unifying dependencies
+#[inline]
+pub(crate) fn compatible_part(version: &semver::Version) -> (u64, u64, u64) {
+    if version.major > 0 {
+        (version.major, 0, 0)
+    } else if version.minor > 0 {
+        (0, version.minor, 0)
+    } else {
+        (0, 0, version.patch)
+    }
+}
+
+impl<N> Krates<N, Edge>
+where
+    N: KrateDetails,
+{
+    /// Gets every crate that has more than one version in the graph, grouped
+    /// by name and the kind of source they come from, along with the
+    /// dependents that require each version.
+    ///
+    /// ```no_run
+    /// use krates::{Compatibility, Krates};
+    ///
+    /// fn print_duplicates(krates: &Krates) {
+    ///     for dupe in krates.duplicates() {
+    ///         if dupe.compatibility == Compatibility::Compatible {
+    ///             println!("{} could be deduplicated", dupe.name);
+    ///         }
+    ///
+    ///         for version in dupe.versions {
+    ///             println!("{} {}", dupe.name, version.krate.version);
+    ///             for dependent in version.dependents {
+    ///                 println!("  required by {} {}", dependent.krate.name, dependent.krate.version);
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn duplicates(&self) -> Vec<Duplicate<'_, N>> {
+        let nodes = &self.graph.raw_nodes()[..self.krates_end];
+        let mut duplicates = Vec::new();
+
+        let name_of = |index: usize| match &nodes[index].weight {
+            Node::Krate { id, .. } => id.name(),
+            Node::Feature { .. } => unreachable!("crate nodes are always first in the graph"),
+        };
+
+        // Crates are sorted by their id, so every version with the same name
+        // is next to each other
+        let mut start = 0;
+        while start < nodes.len() {
+            let name = name_of(start);
+            let end = (start..nodes.len())
+                .find(|index| name_of(*index) != name)
+                .unwrap_or(nodes.len());
+
+            if end - start > 1 {
+                let mut by_source = BTreeMap::<_, Vec<_>>::new();
+                for (index, node) in nodes.iter().enumerate().take(end).skip(start) {
+                    let Node::Krate { id, krate, .. } = &node.weight else {
+                        unreachable!();
+                    };
+
+                    by_source
+                        .entry(id.source_kind())
+                        .or_default()
+                        .push((NodeId::new(index), krate));
+                }
+
+                for (source_kind, mut versions) in by_source {
+                    if versions.len() < 2 {
+                        continue;
+                    }
+
+                    versions.sort_by(|(_, a), (_, b)| a.version().cmp(b.version()));
+
+                    let first = versions[0].1.version();
+                    let compatibility = if versions
+                        .iter()
+                        .all(|(_, k)| compatible_part(k.version()) == compatible_part(first))
+                    {
+                        Compatibility::Compatible
+                    } else if versions
+                        .iter()
+                        .all(|(_, k)| k.version().major == first.major)
+                    {
+                        Compatibility::SameMajor
+                    } else {
+                        Compatibility::DifferentMajor
+                    };
+
+                    duplicates.push(Duplicate {
+                        name,
+                        source_kind,
+                        compatibility,
+                        versions: versions
+                            .into_iter()
+                            .map(|(node_id, krate)| DuplicateVersion {
+                                node_id,
+                                krate,
+                                dependents: self.version_dependents(node_id),
+                            })
+                            .collect(),
+                    });
+                }
+            }
+
+            start = end;
+        }
+
+        duplicates
+    }
+
+    /// Gets the crates that declare a dependency on the specified crate
+    fn version_dependents(&self, nid: NodeId) -> Vec<VersionDependent<'_, N>> {
+        let mut dependents = BTreeMap::<NodeId, Vec<&semver::VersionReq>>::new();
+
+        // Dependencies can be attached to the crate itself, or any of its features
+        let targets = std::iter::once(nid).chain(
+            self.graph
+                .neighbors_directed(nid, Direction::Incoming)
+                .filter(|source| {
+                    matches!(&self.graph[*source], Node::Feature { krate_index, .. } if *krate_index == nid)
+                }),
+        );
+
+        for target in targets {
+            for edge in self.graph.edges_directed(target, Direction::Incoming) {
+                let (Edge::Dep { declaration, .. } | Edge::DepFeature { declaration, .. }) =
+                    edge.weight()
+                else {
+                    continue;
+                };
+
+                let requirements = dependents.entry(edge.source()).or_default();
+                if !requirements.contains(&&declaration.req) {
+                    requirements.push(&declaration.req);
+                }
+            }
+        }
+
+        dependents
+            .into_iter()
+            .filter_map(|(node_id, requirements)| {
+                let Node::Krate { krate, .. } = &self.graph[node_id] else {
+                    return None;
+                };
+
+                Some(VersionDependent {
+                    node_id,
+                    krate,
+                    requirements,
+                })
+            })
+            .collect()
+    }
+}
@@ -3,6 +3,7 @@ use std::fmt;
 
 /// Errors that can occur when acquiring metadata to create a graph from
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// --no-deps was specified when acquiring metadata
     NoResolveGraph,
@@ -10,9 +11,26 @@ pub enum Error {
     Metadata(CMErr),
     /// A package specification was invalid
     InvalidPkgSpec(&'static str),
+    /// A [`crate::Cfg`] was invalid
+    InvalidCfg(&'static str),
+    /// A custom target could not be created
+    InvalidTarget(String),
     /// Due to how the graph was built, all possible root nodes were actually
     /// filtered out, leaving an empty graph
     NoRootKrates,
+    /// Features were specified for a crate that is not a root of the graph
+    UnknownRoot(crate::Kid),
+    /// A feature was specified for a root crate that was either not declared
+    /// in its manifest, or was not resolved by cargo
+    UnknownRootFeature { krate: crate::Kid, feature: String },
+    /// A package id in the resolve graph did not have a corresponding package
+    /// in the metadata
+    MissingPackage(crate::Kid),
+    /// The version in a package id could not be parsed
+    InvalidVersion {
+        krate: crate::Kid,
+        err: semver::Error,
+    },
 }
 
 impl fmt::Display for Error {
@@ -21,7 +39,20 @@ impl fmt::Display for Error {
             Self::NoResolveGraph => f.write_str("no resolution graph was provided"),
             Self::Metadata(err) => write!(f, "{err}"),
             Self::InvalidPkgSpec(err) => write!(f, "package spec was invalid: {err}"),
+            Self::InvalidCfg(err) => write!(f, "cfg was invalid: {err}"),
+            Self::InvalidTarget(err) => write!(f, "target was invalid: {err}"),
             Self::NoRootKrates => f.write_str("no root crates available"),
+            Self::UnknownRoot(kid) => write!(f, "crate '{kid}' is not a root crate"),
+            Self::UnknownRootFeature { krate, feature } => {
+                write!(
+                    f,
+                    "feature '{feature}' is not available for root crate '{krate}'"
+                )
+            }
+            Self::MissingPackage(kid) => write!(f, "package '{kid}' was not found in the metadata"),
+            Self::InvalidVersion { krate, err } => {
+                write!(f, "failed to parse version for '{krate}': {err}")
+            }
         }
     }
 }
@@ -30,11 +61,75 @@ impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Self::Metadata(err) => Some(err),
+            Self::InvalidVersion { err, .. } => Some(err),
             _ => None,
         }
     }
 }
 
+/// A non-fatal issue encountered while building the graph, reported via
+/// [`crate::Builder::on_diagnostic`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Diagnostic {
+    /// The `cfg()` expression of a dependency contained a `target_feature`,
+    /// or another predicate that is not a target predicate, which decided
+    /// whether the dependency was included in the graph or not
+    CfgDecidedEdge {
+        /// The crate that declared the dependency
+        dependent: crate::Kid,
+        /// The crate that was depended upon
+        dependency: crate::Kid,
+        /// The full `cfg()` expression
+        expression: String,
+        /// Whether the dependency edge was included in the graph
+        included: bool,
+    },
+    /// A feature was referenced, either by a feature in the crate's manifest,
+    /// or by a dependency declaration, that was not one of the features
+    /// resolved for the crate, and was ignored
+    UnknownFeature {
+        /// The crate the feature was expected to belong to
+        krate: crate::Kid,
+        /// The name of the feature
+        feature: String,
+    },
+    /// The resolve graph contained a dependency that could not be matched to
+    /// any of the dependencies declared by the dependent, and was ignored
+    UnknownDependency {
+        /// The crate that supposedly declared the dependency
+        dependent: crate::Kid,
+        /// The resolved dependency
+        dependency: crate::Kid,
+    },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CfgDecidedEdge {
+                dependent,
+                dependency,
+                expression,
+                included,
+            } => write!(
+                f,
+                "dependency '{dependency}' of '{dependent}' was {} due to a non-target predicate in '{expression}'",
+                if *included { "included" } else { "excluded" }
+            ),
+            Self::UnknownFeature { krate, feature } => {
+                write!(f, "feature '{feature}' was not resolved for '{krate}'")
+            }
+            Self::UnknownDependency {
+                dependent,
+                dependency,
+            } => write!(
+                f,
+                "resolved dependency '{dependency}' is not declared by '{dependent}'"
+            ),
+        }
+    }
+}
+
 impl From<CMErr> for Error {
     fn from(e: CMErr) -> Self {
         Error::Metadata(e)
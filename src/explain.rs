@@ -0,0 +1,458 @@
+use crate::{Edge, Feature, Kid, Krates, Node, NodeId, ParsedFeature};
+use petgraph::{Direction, visit::EdgeRef};
+use std::collections::{BTreeMap, VecDeque};
+
+/// The reason a feature, or an optional dependency, was activated
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ActivationKind {
+    /// The feature was enabled on a root crate by the user, rather than by
+    /// anything else in the graph, eg. via `--features`, `--all-features`, or
+    /// [`crate::Builder::root_features`]. The source and target of the
+    /// activation are the same node.
+    Cli,
+    /// The `default` feature of a root crate, which is enabled unless the user
+    /// opts out with `--no-default-features`, rather than by anything else in
+    /// the graph. The source and target of the activation are the same node.
+    RootDefault,
+    /// The `default` feature was enabled because the dependency declaration
+    /// did not set `default-features = false`
+    Default,
+    /// The feature is listed in the `features` of the dependency declaration
+    Dependency,
+    /// The feature is enabled by another feature of the same crate, eg.
+    /// `feature = ["other"]`
+    Feature,
+    /// An optional dependency was enabled via a feature, eg. `feature = ["dep:krate"]`.
+    /// The target of the activation is the crate node of the dependency.
+    Krate,
+    /// The feature of a dependency is enabled by a feature, eg.
+    /// `feature = ["krate/feature"]`, which also enables the dependency if it
+    /// is optional
+    Strong,
+    /// The feature of a dependency is enabled by a feature, eg.
+    /// `feature = ["krate?/feature"]`, only because the dependency was
+    /// enabled by something else
+    Weak,
+}
+
+/// A single feature activation in a [`FeatureChain`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FeatureActivation {
+    /// The feature, or crate for dependency declarations, doing the activation
+    pub source: NodeId,
+    /// The feature, or crate for optional dependencies, being activated
+    pub target: NodeId,
+    /// The reason for the activation
+    pub kind: ActivationKind,
+}
+
+/// A chain of activations that led to a feature being enabled
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeatureChain {
+    /// The activations, in order, from the origin of the chain to the feature.
+    ///
+    /// The origin is either a [`ActivationKind::Cli`] or
+    /// [`ActivationKind::RootDefault`] feature, or a dependency
+    /// declaration of a crate that is not optional, see
+    /// [`Krates::shortest_path_to`] for why that crate is in the graph
+    pub activations: Vec<FeatureActivation>,
+}
+
+/// The position when walking activations backwards
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Cause {
+    /// A feature node, activated by other features or dependency declarations
+    Feature(NodeId),
+    /// An optional dependency of a crate, activated by the crate's features
+    Optional {
+        dependent: NodeId,
+        dependency: NodeId,
+    },
+}
+
+impl Krates<crate::Package, Edge> {
+    /// Explains why a feature is enabled on a crate, returning a chain of
+    /// activations for each way the feature is directly activated, each one
+    /// traced back to its origin via the fewest activations.
+    ///
+    /// Returns `None` if the crate is not in the graph, or the feature is
+    /// not enabled. Graphs built with [`crate::Builder::crates_only`] have no
+    /// feature nodes, so this always returns `None` for them.
+    ///
+    /// ```no_run
+    /// use krates::{ActivationKind, Krates, Kid, Node, NodeId};
+    ///
+    /// fn explain(krates: &Krates, kid: &Kid, feature: &str) {
+    ///     let name = |nid: NodeId| match &krates.graph()[nid] {
+    ///         Node::Krate { krate, .. } => krate.name.clone(),
+    ///         Node::Feature { krate_index, name } => {
+    ///             format!("{}/{name}", krates[*krate_index].name)
+    ///         }
+    ///     };
+    ///
+    ///     for chain in krates.explain_feature(kid, feature).unwrap() {
+    ///         for act in chain.activations {
+    ///             let (source, target) = (name(act.source), name(act.target));
+    ///             match act.kind {
+    ///                 ActivationKind::Cli => println!("{source} is enabled by the user"),
+    ///                 ActivationKind::RootDefault => println!("{source} is enabled by default"),
+    ///                 ActivationKind::Weak => {
+    ///                     println!("{source} enables {target} since it is already used")
+    ///                 }
+    ///                 kind => println!("{source} enables {target} ({kind:?})"),
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn explain_feature(&self, kid: &Kid, feature: &str) -> Option<Vec<FeatureChain>> {
+        let (nid, _) = self.get_node(kid, Some(feature))?;
+
+        let direct = self.activations(nid);
+        if direct.is_empty() {
+            return Some(vec![FeatureChain {
+                activations: vec![self.root_activation(nid)],
+            }]);
+        }
+
+        Some(
+            direct
+                .into_iter()
+                .map(|act| {
+                    let mut activations = self.origin(act);
+                    activations.push(act);
+                    FeatureChain { activations }
+                })
+                .collect(),
+        )
+    }
+
+    /// Finds the shortest chain of activations that leads to the source of
+    /// the specified activation
+    fn origin(&self, act: FeatureActivation) -> Vec<FeatureActivation> {
+        let Some(start) = self.cause(&act) else {
+            return Vec::new();
+        };
+
+        let mut parents = BTreeMap::<Cause, (FeatureActivation, Cause)>::new();
+        let mut queue = VecDeque::from([start]);
+
+        let chain = |parents: &BTreeMap<Cause, (FeatureActivation, Cause)>,
+                     mut cause: Cause,
+                     mut chain: Vec<FeatureActivation>| {
+            while let Some((act, next)) = parents.get(&cause) {
+                chain.push(*act);
+                cause = *next;
+            }
+            chain
+        };
+
+        while let Some(cause) = queue.pop_front() {
+            let activations = match cause {
+                Cause::Feature(nid) => {
+                    let activations = self.activations(nid);
+                    if activations.is_empty() {
+                        return chain(&parents, cause, vec![self.root_activation(nid)]);
+                    }
+                    activations
+                }
+                Cause::Optional {
+                    dependent,
+                    dependency,
+                } => {
+                    let activations = self.optional_activations(dependent, dependency);
+                    if activations.is_empty() {
+                        return chain(&parents, cause, Vec::new());
+                    }
+                    activations
+                }
+            };
+
+            for act in activations {
+                let Some(next) = self.cause(&act) else {
+                    return chain(&parents, cause, vec![act]);
+                };
+
+                if next != start && !parents.contains_key(&next) {
+                    parents.insert(next, (act, cause));
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        // Every cause was already visited, which can only happen if features
+        // form a cycle without an origin
+        chain(&parents, start, Vec::new())
+    }
+
+    /// Creates the activation for a feature that wasn't activated by anything
+    /// else in the graph, and so must have been enabled on a root crate.
+    ///
+    /// The `default` feature is enabled by default, unless the root's features
+    /// were overridden without it, in which case the user asked for it
+    fn root_activation(&self, nid: NodeId) -> FeatureActivation {
+        let is_default = |krate_index: NodeId| match &self.graph[krate_index] {
+            Node::Krate { id, .. } => self.no_default_roots.binary_search(id).is_err(),
+            Node::Feature { .. } => false,
+        };
+
+        let kind = match &self.graph[nid] {
+            Node::Feature { krate_index, name }
+                if name == "default" && is_default(*krate_index) =>
+            {
+                ActivationKind::RootDefault
+            }
+            _ => ActivationKind::Cli,
+        };
+
+        FeatureActivation {
+            source: nid,
+            target: nid,
+            kind,
+        }
+    }
+
+    /// Gets what caused the source of the activation, or `None` if the source
+    /// is the origin of a chain
+    fn cause(&self, act: &FeatureActivation) -> Option<Cause> {
+        match &self.graph[act.source] {
+            Node::Feature { .. }
+                if !matches!(act.kind, ActivationKind::Cli | ActivationKind::RootDefault) =>
+            {
+                Some(Cause::Feature(act.source))
+            }
+            Node::Krate { .. }
+                if matches!(
+                    act.kind,
+                    ActivationKind::Default | ActivationKind::Dependency
+                ) =>
+            {
+                let dependency = self.owner(act.target);
+                self.is_optional(act.source, dependency)
+                    .then_some(Cause::Optional {
+                        dependent: act.source,
+                        dependency,
+                    })
+            }
+            _ => None,
+        }
+    }
+
+    /// Gets the direct activations of a feature node
+    fn activations(&self, target: NodeId) -> Vec<FeatureActivation> {
+        let Node::Feature {
+            krate_index: owner,
+            name: feature,
+        } = &self.graph[target]
+        else {
+            return Vec::new();
+        };
+
+        let mut activations = Vec::new();
+        let mut by_crates = Vec::new();
+
+        for edge in self.graph.edges_directed(target, Direction::Incoming) {
+            let source = edge.source();
+            let kind = match (&self.graph[source], edge.weight()) {
+                (Node::Feature { krate_index, name }, _) => {
+                    if krate_index == owner {
+                        ActivationKind::Feature
+                    } else {
+                        self.feature_reference(*krate_index, name, *owner, feature)
+                            .unwrap_or(ActivationKind::Strong)
+                    }
+                }
+                (Node::Krate { .. }, Edge::DepFeature { declaration, .. }) => {
+                    if feature == "default"
+                        && declaration.uses_default_features
+                        && !declaration.features.iter().any(|f| f == "default")
+                    {
+                        ActivationKind::Default
+                    } else {
+                        ActivationKind::Dependency
+                    }
+                }
+                (Node::Krate { .. }, Edge::Feature) => {
+                    by_crates.push(source);
+                    continue;
+                }
+                (Node::Krate { .. }, Edge::Dep { .. }) => continue,
+            };
+
+            activations.push(FeatureActivation {
+                source,
+                target,
+                kind,
+            });
+        }
+
+        // Features enabled by a crate's own features, eg. `krate?/feature`, are
+        // not always linked from the feature node that enabled them, so find
+        // the enabled features of the crate that refer to this one
+        for krate in by_crates {
+            let mut referenced = false;
+
+            for edge in self.graph.edges_directed(krate, Direction::Incoming) {
+                let Node::Feature { krate_index, name } = &self.graph[edge.source()] else {
+                    continue;
+                };
+
+                if *krate_index != krate {
+                    continue;
+                }
+
+                if let Some(kind) = self.feature_reference(krate, name, *owner, feature) {
+                    referenced = true;
+                    activations.push(FeatureActivation {
+                        source: edge.source(),
+                        target,
+                        kind,
+                    });
+                }
+            }
+
+            if !referenced {
+                activations.push(FeatureActivation {
+                    source: krate,
+                    target,
+                    kind: ActivationKind::Strong,
+                });
+            }
+        }
+
+        // A crate can depend on the same crate several times, eg. as both a
+        // normal and a build dependency
+        let mut deduped = Vec::with_capacity(activations.len());
+        for act in activations {
+            if !deduped.contains(&act) {
+                deduped.push(act);
+            }
+        }
+
+        deduped
+    }
+
+    /// Gets the features of a crate that enabled one of its optional dependencies
+    fn optional_activations(
+        &self,
+        dependent: NodeId,
+        dependency: NodeId,
+    ) -> Vec<FeatureActivation> {
+        let mut activations = Vec::new();
+
+        let mut add = |target: NodeId, strong_only: bool| {
+            for edge in self.graph.edges_directed(target, Direction::Incoming) {
+                let Node::Feature { krate_index, name } = &self.graph[edge.source()] else {
+                    continue;
+                };
+
+                if *krate_index != dependent {
+                    continue;
+                }
+
+                let kind = if strong_only {
+                    let Node::Feature { name: feature, .. } = &self.graph[target] else {
+                        continue;
+                    };
+
+                    if self.feature_reference(dependent, name, dependency, feature)
+                        != Some(ActivationKind::Strong)
+                    {
+                        continue;
+                    }
+
+                    ActivationKind::Strong
+                } else {
+                    ActivationKind::Krate
+                };
+
+                activations.push(FeatureActivation {
+                    source: edge.source(),
+                    target,
+                    kind,
+                });
+            }
+        };
+
+        add(dependency, false);
+
+        for edge in self.graph.edges_directed(dependency, Direction::Incoming) {
+            if matches!(&self.graph[edge.source()], Node::Feature { krate_index, .. } if *krate_index == dependency)
+            {
+                add(edge.source(), true);
+            }
+        }
+
+        activations
+    }
+
+    /// Checks how the feature of a crate refers to the feature of a dependency
+    fn feature_reference(
+        &self,
+        krate: NodeId,
+        feature: &str,
+        dependency: NodeId,
+        dependency_feature: &str,
+    ) -> Option<ActivationKind> {
+        let Node::Krate { krate: pkg, .. } = &self.graph[krate] else {
+            return None;
+        };
+
+        pkg.features.get(feature)?.iter().find_map(|sub| {
+            match ParsedFeature::from(sub.as_str()).feat() {
+                Feature::Strong {
+                    krate: name,
+                    feature,
+                } if feature == dependency_feature && self.resolves_to(krate, name, dependency) => {
+                    Some(ActivationKind::Strong)
+                }
+                Feature::Weak {
+                    krate: name,
+                    feature,
+                } if feature == dependency_feature && self.resolves_to(krate, name, dependency) => {
+                    Some(ActivationKind::Weak)
+                }
+                _ => None,
+            }
+        })
+    }
+
+    /// Checks if the dependency name used in a crate's features resolved to
+    /// the specified crate
+    fn resolves_to(&self, krate: NodeId, name: &str, dependency: NodeId) -> bool {
+        let Node::Krate {
+            krate: pkg,
+            dep_mapping,
+            ..
+        } = &self.graph[krate]
+        else {
+            return false;
+        };
+
+        pkg.dependencies.iter().enumerate().any(|(i, dep)| {
+            dep.rename.as_deref().unwrap_or(&dep.name) == name
+                && dep_mapping.get(i).copied().flatten() == Some(dependency)
+        })
+    }
+
+    /// Checks if every declaration of the dependency in the crate is optional
+    fn is_optional(&self, krate: NodeId, dependency: NodeId) -> bool {
+        let Node::Krate {
+            krate: pkg,
+            dep_mapping,
+            ..
+        } = &self.graph[krate]
+        else {
+            return false;
+        };
+
+        let mut declarations = pkg
+            .dependencies
+            .iter()
+            .zip(dep_mapping)
+            .filter(|(_, nid)| **nid == Some(dependency))
+            .peekable();
+
+        declarations.peek().is_some() && declarations.all(|(dep, _)| dep.optional)
+    }
+}
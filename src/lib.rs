@@ -42,17 +42,37 @@ pub use camino::{self, Utf8Path, Utf8PathBuf};
 use petgraph::{Direction, graph::EdgeIndex, graph::NodeIndex, visit::EdgeRef};
 
 mod builder;
+mod cycles;
+mod diff;
+mod duplicates;
 mod errors;
+mod explain;
+mod order;
+mod paths;
 mod pkgspec;
+#[cfg(feature = "serialize")]
+mod ser;
+mod subgraph;
+mod tree;
+mod units;
 
 pub use builder::{
-    Builder, Cmd, LockOptions, NoneFilter, OnFilter, Scope, Target,
+    Builder, Cfg, Cmd, CustomTarget, DefaultFactory, EdgeContext, EdgeFactory, LockOptions,
+    NodeContext, NodeFactory, NoneFilter, OnFilter, Scope, Target,
     features::{Feature, ParsedFeature},
     index,
 };
-pub use errors::Error;
+pub use cycles::{Cycle, CycleDependency};
+pub use diff::{Change, DependencyChange, Diff, FeatureChange, KrateChange, VersionChange, diff};
+pub use duplicates::{Compatibility, Duplicate, DuplicateVersion, VersionDependent};
+pub use errors::{Diagnostic, Error};
+pub use explain::{ActivationKind, FeatureActivation, FeatureChain};
+pub use order::{BrokenDependency, Levels, TopoOrder};
+pub use paths::{DependencyPath, PathHop};
 pub use pkgspec::PkgSpec;
-use std::fmt;
+use std::{fmt, sync::Arc};
+pub use subgraph::SubgraphOptions;
+pub use tree::{ReverseDependent, ReverseTree};
 
 /// A crate's unique identifier
 #[derive(Clone, Default)]
@@ -84,6 +104,34 @@ impl Kid {
         let (s, e) = self.components[2];
         &self.repr[s..e]
     }
+
+    /// Gets the kind of source the package comes from
+    #[inline]
+    pub fn source_kind(&self) -> SourceKind {
+        let source = self.source();
+        if source.starts_with("registry+") || source.starts_with("sparse+") {
+            SourceKind::Registry
+        } else if source.starts_with("git+") {
+            SourceKind::Git
+        } else if source.starts_with("path+") {
+            SourceKind::Path
+        } else {
+            SourceKind::Other
+        }
+    }
+}
+
+/// The kind of source a package comes from
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SourceKind {
+    /// A registry, eg. crates.io
+    Registry,
+    /// A git repository
+    Git,
+    /// A local path
+    Path,
+    /// Any other source, eg. a local registry or a directory of vendored crates
+    Other,
 }
 
 #[allow(clippy::fallible_impl_from)]
@@ -289,6 +337,32 @@ impl PartialEq for Kid {
 /// The set of features that have been enabled on a crate
 pub type EnabledFeatures = std::collections::BTreeSet<String>;
 
+/// The features enabled on a crate for each kind of compile unit it is built
+/// as, rather than the single unified set that `cargo metadata` reports.
+///
+/// This mirrors the feature unification done by cargo's [version 2 resolver](https://doc.rust-lang.org/cargo/reference/resolver.html#feature-resolver-version-2),
+/// where features enabled on build dependencies and proc-macros are not
+/// unified with those enabled on normal dependencies, and features enabled by
+/// dev-dependencies are only used when building tests and examples.
+///
+/// Each set is `None` if the crate is not built at all for that kind of unit.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnitFeatures {
+    /// The features enabled when the crate is built for the target in a
+    /// normal build, eg. `cargo build`
+    pub target: Option<EnabledFeatures>,
+    /// The features enabled when the crate is built for the host, ie. it is a
+    /// build dependency, a proc-macro, or a dependency of either
+    pub host: Option<EnabledFeatures>,
+    /// The features enabled when the crate is built for the target with the
+    /// dev-dependencies of the workspace crates also enabled, eg. `cargo test`
+    pub dev: Option<EnabledFeatures>,
+    /// The features enabled when the crate is built for the host with the
+    /// dev-dependencies of the workspace crates also enabled, which includes
+    /// proc-macros that are only dev-dependencies, and their dependencies
+    pub dev_host: Option<EnabledFeatures>,
+}
+
 /// The dependency kind. A crate can depend on the same crate multiple times
 /// with different dependency kinds
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -340,11 +414,17 @@ pub enum Node<N> {
         krate: N,
         /// List of features enabled on the crate
         features: EnabledFeatures,
+        /// The features enabled on the crate for each kind of compile unit,
+        /// only present if [`Builder::unit_features`] was enabled
+        unit_features: Option<Box<UnitFeatures>>,
         /// Mapping to the exact node that a dependency is resolved to.
         ///
         /// This can be used manually but the helper `[Krates::resolved_dependency`]
         /// is easier to use
         dep_mapping: Vec<Option<NodeId>>,
+        /// The minimum number of dependency edges between this crate and any
+        /// of the roots of the graph, which is 0 for the roots themselves
+        depth: usize,
     },
     Feature {
         /// The node index for the crate this feature is for
@@ -385,6 +465,28 @@ where
     }
 }
 
+/// The dependency declared in a crate's manifest that resulted in an
+/// [`Edge::Dep`] or [`Edge::DepFeature`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepDeclaration {
+    /// The index of the dependency in the dependent's [`Package::dependencies`],
+    /// which can also be used with [`Krates::resolved_dependency`]
+    pub index: usize,
+    /// The version requirement for the dependency
+    pub req: semver::VersionReq,
+    /// The name the dependency was renamed to, if it was renamed
+    pub rename: Option<String>,
+    /// Whether the dependency is optional
+    pub optional: bool,
+    /// Whether the default features of the dependency are enabled
+    pub uses_default_features: bool,
+    /// The features explicitly enabled on the dependency
+    pub features: Vec<String>,
+    /// The URL of the index of the registry the dependency is from, `None`
+    /// if it is from crates.io
+    pub registry: Option<String>,
+}
+
 /// The default type used for edges in the crate graph.
 #[derive(Debug, Clone)]
 pub enum Edge {
@@ -393,6 +495,9 @@ pub enum Edge {
         kind: DepKind,
         /// A possible `cfg()` or <target-triple> applied to this dependency
         cfg: Option<String>,
+        /// The dependency declaration this edge was created from, which is
+        /// shared by every edge created from the same declaration
+        declaration: Arc<DepDeclaration>,
     },
     /// An edge from one feature to another
     Feature,
@@ -401,12 +506,15 @@ pub enum Edge {
         kind: DepKind,
         /// A possible `cfg()` or <target-triple> applied to this dependency
         cfg: Option<String>,
+        /// The dependency declaration this edge was created from, which is
+        /// shared by every edge created from the same declaration
+        declaration: Arc<DepDeclaration>,
     },
 }
 
 impl fmt::Display for Edge {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if let Self::DepFeature { kind, cfg } | Self::Dep { kind, cfg } = self {
+        if let Self::DepFeature { kind, cfg, .. } | Self::Dep { kind, cfg, .. } = self {
             match kind {
                 DepKind::Normal => {}
                 DepKind::Build => f.write_str("(build)")?,
@@ -424,13 +532,64 @@ impl fmt::Display for Edge {
 
 /// A crate graph. Each unique crate is a node, and each unique dependency
 /// between 2 crates is an edge.
+///
+/// The graph can contain cycles, as cargo allows a crate to have a
+/// dev-dependency on a crate that depends on it, see [`Krates::cycles`]. Every
+/// traversal provided by this type handles cycles.
 pub struct Krates<N = cm::Package, E = Edge> {
     graph: petgraph::Graph<Node<N>, E, petgraph::Directed, u32>,
     workspace_members: Vec<Kid>,
     workspace_root: Utf8PathBuf,
+    /// The roots whose features were overridden via
+    /// [`Builder::root_features`] without their default features, so their
+    /// `default` feature is only enabled if the user asked for it
+    no_default_roots: Vec<Kid>,
     /// We split the graph between crate and feature nodes, but keep the crates
     /// grouped together in the front since most queries are against them
     krates_end: usize,
+    lookups: Lookups,
+}
+
+/// Lookup tables for the graph, so that finding crates by name, or a feature
+/// of a crate, doesn't need to scan the graph
+#[derive(Clone, Default)]
+struct Lookups {
+    /// The crate nodes with each [`KrateDetails::name`], which is not
+    /// necessarily the name in the package id. This is only built the first
+    /// time crates are looked up by name, as it needs the name from the node
+    names: std::sync::OnceLock<std::collections::BTreeMap<String, Vec<NodeId>>>,
+    /// The feature nodes of each crate, indexed by the crate's node index
+    features: Vec<std::collections::BTreeMap<String, NodeId>>,
+}
+
+impl Lookups {
+    fn new<N, E>(graph: &petgraph::Graph<Node<N>, E>, krates_end: usize) -> Self {
+        let mut features = vec![std::collections::BTreeMap::new(); krates_end];
+
+        for (index, node) in graph.raw_nodes()[krates_end..].iter().enumerate() {
+            if let Node::Feature { krate_index, name } = &node.weight {
+                // The same feature can have more than one node, always use
+                // the first one
+                features[krate_index.index()]
+                    .entry(name.clone())
+                    .or_insert(NodeId::new(krates_end + index));
+            }
+        }
+
+        Self {
+            names: std::sync::OnceLock::new(),
+            features,
+        }
+    }
+
+    /// The lookups for the graph with the crates converted to a different
+    /// type, which may have different names
+    fn mapped(&self) -> Self {
+        Self {
+            names: std::sync::OnceLock::new(),
+            features: self.features.clone(),
+        }
+    }
 }
 
 #[allow(clippy::len_without_is_empty)]
@@ -569,6 +728,71 @@ impl<N, E> Krates<N, E> {
         direct_dependents
     }
 
+    /// Gets the crates that are reachable from the specified roots, including
+    /// the roots themselves, by only walking the edges that match the predicate.
+    /// The crates are sorted by their node id.
+    ///
+    /// The predicate is called with the crate the edge comes from, ie. the
+    /// crate the feature belongs to if the edge comes from a feature node, and
+    /// the edge itself.
+    ///
+    /// ```no_run
+    /// use krates::{DepKind, Edge, Krates};
+    ///
+    /// /// Gets the crates used by the workspace when building for Linux, while
+    /// /// ignoring the build dependencies of non-workspace crates
+    /// fn linux_crates(krates: &Krates) -> usize {
+    ///     let roots: Vec<_> = krates
+    ///         .workspace_members()
+    ///         .filter_map(|node| match node {
+    ///             krates::Node::Krate { id, .. } => krates.nid_for_kid(id),
+    ///             krates::Node::Feature { .. } => None,
+    ///         })
+    ///         .collect();
+    ///
+    ///     krates
+    ///         .reachable(roots.iter().copied(), |source, edge| match edge {
+    ///             Edge::Dep { kind, cfg, .. } | Edge::DepFeature { kind, cfg, .. } => {
+    ///                 (*kind != DepKind::Build || roots.contains(&source))
+    ///                     && cfg.as_deref().is_none_or(|cfg| cfg.contains("linux"))
+    ///             }
+    ///             Edge::Feature => true,
+    ///         })
+    ///         .len()
+    /// }
+    /// ```
+    pub fn reachable(
+        &self,
+        roots: impl IntoIterator<Item = NodeId>,
+        mut predicate: impl FnMut(NodeId, &E) -> bool,
+    ) -> Vec<NodeId> {
+        let mut visited = vec![false; self.graph.node_count()];
+        let mut stack: Vec<_> = roots
+            .into_iter()
+            .filter(|nid| nid.index() < self.krates_end)
+            .collect();
+
+        for nid in &stack {
+            visited[nid.index()] = true;
+        }
+
+        while let Some(nid) = stack.pop() {
+            let source = self.owner(nid);
+
+            for edge in self.graph.edges_directed(nid, Direction::Outgoing) {
+                if !visited[edge.target().index()] && predicate(source, edge.weight()) {
+                    visited[edge.target().index()] = true;
+                    stack.push(edge.target());
+                }
+            }
+        }
+
+        (0..self.krates_end)
+            .filter(|index| visited[*index])
+            .map(NodeId::new)
+            .collect()
+    }
+
     /// Retrieves the krate that was resolved for the specified crate dependency.
     ///
     /// This will return `None` if the krate doesn't exist, the dependency doesn't
@@ -621,17 +845,8 @@ impl<N, E> Krates<N, E> {
     pub fn get_node(&self, kid: &Kid, feature: Option<&str>) -> Option<(NodeId, &Node<N>)> {
         self.nid_for_kid(kid).and_then(|nid| {
             if let Some(feat) = feature {
-                self.graph
-                    .edges_directed(nid, Direction::Incoming)
-                    .find_map(|edge| {
-                        if let Node::Feature { krate_index, name } = &self.graph[edge.source()] {
-                            if *krate_index == nid && name == feat {
-                                return Some((edge.source(), &self.graph[edge.source()]));
-                            }
-                        }
-
-                        None
-                    })
+                let fid = *self.lookups.features[nid.index()].get(feat)?;
+                Some((fid, &self.graph[fid]))
             } else {
                 Some((nid, &self.graph[nid]))
             }
@@ -650,6 +865,22 @@ impl<N, E> Krates<N, E> {
         })
     }
 
+    /// Gets the features enabled for the specified crate for each kind of
+    /// compile unit it is built as.
+    ///
+    /// This will return `None` if the crate doesn't exist, or the graph was not
+    /// built with [`Builder::unit_features`] enabled.
+    #[inline]
+    pub fn get_unit_features(&self, kid: &Kid) -> Option<&UnitFeatures> {
+        self.node_for_kid(kid).and_then(|node| {
+            if let Node::Krate { unit_features, .. } = node {
+                unit_features.as_deref()
+            } else {
+                unreachable!()
+            }
+        })
+    }
+
     /// Get an iterator over the nodes for the members of the workspace
     #[inline]
     pub fn workspace_members(&self) -> impl Iterator<Item = &Node<N>> {
@@ -657,6 +888,115 @@ impl<N, E> Krates<N, E> {
             .iter()
             .filter_map(move |pid| self.nid_for_kid(pid).map(|ind| &self.graph[ind]))
     }
+
+    /// Creates a new graph with the same crates, features, and edges, but with
+    /// each crate and edge converted to a different type. Every [`NodeId`] and
+    /// [`EdgeId`] in the new graph refers to the same node or edge.
+    ///
+    /// ```no_run
+    /// use krates::{Edge, Kid, Krates};
+    ///
+    /// struct Summary {
+    ///     name: String,
+    ///     version: krates::semver::Version,
+    /// }
+    ///
+    /// fn summarize(krates: &Krates) -> Krates<Summary, Edge> {
+    ///     krates.map(
+    ///         |_kid: &Kid, krate: &krates::Package| Summary {
+    ///             name: krate.name.clone(),
+    ///             version: krate.version.clone(),
+    ///         },
+    ///         |edge: &Edge| edge.clone(),
+    ///     )
+    /// }
+    /// ```
+    pub fn map<N2, E2>(
+        &self,
+        mut node_map: impl FnMut(&Kid, &N) -> N2,
+        mut edge_map: impl FnMut(&E) -> E2,
+    ) -> Krates<N2, E2> {
+        let graph = self.graph.map(
+            |_nid, node| map_node(node, |id, krate| node_map(id, krate)),
+            |_eid, edge| edge_map(edge),
+        );
+
+        Krates {
+            graph,
+            workspace_members: self.workspace_members.clone(),
+            workspace_root: self.workspace_root.clone(),
+            no_default_roots: self.no_default_roots.clone(),
+            krates_end: self.krates_end,
+            lookups: self.lookups.mapped(),
+        }
+    }
+
+    /// The same as [`Krates::map`], except each conversion can fail, in which
+    /// case the first error is returned.
+    pub fn try_map<N2, E2, Err>(
+        &self,
+        mut node_map: impl FnMut(&Kid, &N) -> Result<N2, Err>,
+        mut edge_map: impl FnMut(&E) -> Result<E2, Err>,
+    ) -> Result<Krates<N2, E2>, Err> {
+        let mut graph =
+            petgraph::Graph::with_capacity(self.graph.node_count(), self.graph.edge_count());
+
+        for node in self.graph.raw_nodes() {
+            let node = match &node.weight {
+                Node::Krate { id, krate, .. } => {
+                    let krate = node_map(id, krate)?;
+                    map_node(&node.weight, |_id, _krate| krate)
+                }
+                Node::Feature { krate_index, name } => Node::Feature {
+                    krate_index: *krate_index,
+                    name: name.clone(),
+                },
+            };
+
+            graph.add_node(node);
+        }
+
+        // Adding the edges in the same order results in the same edge ids, as
+        // well as the same order when iterating the edges of a node
+        for edge in self.graph.raw_edges() {
+            graph.add_edge(edge.source(), edge.target(), edge_map(&edge.weight)?);
+        }
+
+        Ok(Krates {
+            graph,
+            workspace_members: self.workspace_members.clone(),
+            workspace_root: self.workspace_root.clone(),
+            no_default_roots: self.no_default_roots.clone(),
+            krates_end: self.krates_end,
+            lookups: self.lookups.mapped(),
+        })
+    }
+}
+
+/// Clones a node, converting the crate to a different type
+#[inline]
+fn map_node<N, N2>(node: &Node<N>, node_map: impl FnOnce(&Kid, &N) -> N2) -> Node<N2> {
+    match node {
+        Node::Krate {
+            id,
+            krate,
+            features,
+            unit_features,
+            dep_mapping,
+            depth,
+        } => Node::Krate {
+            id: id.clone(),
+            krate: node_map(id, krate),
+            features: features.clone(),
+            unit_features: unit_features.clone(),
+            dep_mapping: dep_mapping.clone(),
+            depth: *depth,
+        },
+        Node::Feature { krate_index, name } => Node::Feature {
+            krate_index: *krate_index,
+            name: name.clone(),
+        },
+    }
 }
 
 /// A direct dependency of a crate
@@ -708,53 +1048,18 @@ where
     /// This gives the same output as if the graph had been built by using
     /// [`ignore_kind`](crate::Builder::ignore_kind) with [`Scope::all`](crate::Scope::All)
     pub fn krates_filtered(&self, filter: DepKind) -> Vec<&N> {
-        let graph = self.graph();
-        let mut filtered: std::collections::BTreeMap<_, _> = self
-            .workspace_members()
-            .filter_map(|n| {
-                let Node::Krate { id, krate, .. } = n else {
-                    return None;
-                };
-                Some((id, krate))
-            })
-            .collect();
-        let mut stack: Vec<_> = self
+        let roots = self
             .workspace_members
             .iter()
-            .filter_map(|pid| self.nid_for_kid(pid))
-            .collect();
-        let mut visited = std::collections::BTreeSet::new();
-
-        while let Some(nid) = stack.pop() {
-            for edge in graph.edges_directed(nid, Direction::Outgoing) {
-                match edge.weight() {
-                    Edge::Dep { kind, .. } | Edge::DepFeature { kind, .. } => {
-                        if *kind == filter {
-                            continue;
-                        }
-                    }
-                    Edge::Feature => {}
-                };
+            .filter_map(|pid| self.nid_for_kid(pid));
 
-                match &graph[edge.target()] {
-                    Node::Krate { id, krate, .. } => {
-                        if visited.insert(edge.target()) {
-                            stack.push(edge.target());
-                            filtered.insert(id, krate);
-                        }
-                    }
-                    Node::Feature { .. } => {
-                        if visited.insert(edge.target()) {
-                            stack.push(edge.target());
-                        }
-                    }
-                }
-            }
-
-            visited.insert(nid);
-        }
-
-        filtered.into_values().collect()
+        self.reachable(roots, |_source, edge| match edge {
+            Edge::Dep { kind, .. } | Edge::DepFeature { kind, .. } => *kind != filter,
+            Edge::Feature => true,
+        })
+        .into_iter()
+        .map(|nid| &self[nid])
+        .collect()
     }
 }
 
@@ -782,31 +1087,16 @@ where
         name: impl Into<String>,
         req: semver::VersionReq,
     ) -> impl Iterator<Item = KrateMatch<'_, N>> {
-        let raw_nodes = &self.graph.raw_nodes()[0..self.krates_end];
-
-        let name = name.into();
-
-        raw_nodes
-            .iter()
-            .enumerate()
-            .filter_map(move |(index, node)| {
-                if let Node::Krate { krate, id, .. } = &node.weight {
-                    if krate.name() == name && req.matches(krate.version()) {
-                        return Some(KrateMatch {
-                            node_id: NodeId::new(index),
-                            krate,
-                            kid: id,
-                        });
-                    }
-                }
-
-                None
-            })
+        self.krates_by_name(name)
+            .filter(move |km| req.matches(km.krate.version()))
     }
 
     /// Get an iterator over all of the crates in the graph with the given name,
     /// in the case there are multiple versions, or sources, of the crate.
     ///
+    /// Crates are matched by their [`KrateDetails::name`], which for
+    /// [`Package`] is the name in its manifest rather than its package id.
+    ///
     /// ```
     /// use krates::Krates;
     ///
@@ -820,26 +1110,34 @@ where
         &self,
         name: impl Into<String>,
     ) -> impl Iterator<Item = KrateMatch<'_, N>> {
-        let raw_nodes = &self.graph.raw_nodes()[0..self.krates_end];
+        let names = self.lookups.names.get_or_init(|| {
+            let mut names = std::collections::BTreeMap::<String, Vec<NodeId>>::new();
+            for (index, node) in self.graph.raw_nodes()[..self.krates_end].iter().enumerate() {
+                if let Node::Krate { krate, .. } = &node.weight {
+                    names
+                        .entry(krate.name().to_owned())
+                        .or_default()
+                        .push(NodeId::new(index));
+                }
+            }
+            names
+        });
 
-        let name = name.into();
+        let nids = names
+            .get(&name.into())
+            .map_or(&[][..], |nids| nids.as_slice());
 
-        raw_nodes
-            .iter()
-            .enumerate()
-            .filter_map(move |(index, node)| {
-                if let Node::Krate { krate, id, .. } = &node.weight {
-                    if krate.name() == name {
-                        return Some(KrateMatch {
-                            node_id: NodeId::new(index),
-                            krate,
-                            kid: id,
-                        });
-                    }
-                }
+        nids.iter().filter_map(|nid| {
+            let Node::Krate { krate, id, .. } = &self.graph[*nid] else {
+                return None;
+            };
 
-                None
+            Some(KrateMatch {
+                node_id: *nid,
+                krate,
+                kid: id,
             })
+        })
     }
 }
 
@@ -0,0 +1,201 @@
+use crate::{DepKind, Edge, Krates, NodeId};
+use std::collections::BTreeMap;
+
+/// A dependency that was ignored to break a cycle when ordering crates
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BrokenDependency {
+    /// The crate with the dependency
+    pub dependent: NodeId,
+    /// The crate being depended upon
+    pub dependency: NodeId,
+    /// True if the dependency was only a dev-dependency, which is the only
+    /// kind of cycle cargo allows
+    pub dev_only: bool,
+}
+
+/// The crates in an order where every crate comes after its dependencies,
+/// see [`Krates::topo_order`]
+pub struct TopoOrder {
+    /// The crates, dependencies first
+    pub order: Vec<NodeId>,
+    /// The dependencies that were ignored to break cycles
+    pub broken: Vec<BrokenDependency>,
+}
+
+/// The crates grouped into levels, see [`Krates::levels`]
+pub struct Levels {
+    /// Each level of crates, where every crate only depends on crates in
+    /// previous levels, so that every crate in a level can be processed in
+    /// parallel once the previous levels are done
+    pub levels: Vec<Vec<NodeId>>,
+    /// The dependencies that were ignored to break cycles
+    pub broken: Vec<BrokenDependency>,
+}
+
+impl<N> Krates<N, Edge> {
+    /// Gets the crates in an order where every crate comes after all of its
+    /// dependencies, which is every level of [`Self::levels`] in order.
+    ///
+    /// See [`Self::levels`] for how cycles are broken.
+    ///
+    /// ```no_run
+    /// use krates::Krates;
+    ///
+    /// fn analyze(krates: &Krates) {
+    ///     let topo = krates.topo_order();
+    ///
+    ///     for broken in topo.broken {
+    ///         println!(
+    ///             "ignored {}'s dependency on {}",
+    ///             krates[broken.dependent].name,
+    ///             krates[broken.dependency].name,
+    ///         );
+    ///     }
+    ///
+    ///     for nid in topo.order {
+    ///         println!("analyzing {}", krates[nid].name);
+    ///     }
+    /// }
+    /// ```
+    pub fn topo_order(&self) -> TopoOrder {
+        let Levels { levels, broken } = self.levels();
+
+        TopoOrder {
+            order: levels.into_iter().flatten().collect(),
+            broken,
+        }
+    }
+
+    /// Groups the crates into levels, where every crate only depends on crates
+    /// in previous levels. The first level contains the crates with no
+    /// dependencies, and each crate is in the earliest level possible. Crates
+    /// in the same level are sorted by their node id.
+    ///
+    /// Cargo allows cycles as long as they go through a dev-dependency, eg. a
+    /// crate can have a dev-dependency on a crate that depends on it. To break
+    /// these cycles, every dependency that is only a dev-dependency, between
+    /// crates that are part of the same cycle, is ignored. If a cycle still
+    /// remains, which cargo does not allow, the remaining dependencies of the
+    /// crate with the lowest node id that is not yet placed are ignored.
+    /// Every ignored dependency is reported in [`Levels::broken`].
+    ///
+    /// ```no_run
+    /// use krates::Krates;
+    ///
+    /// fn schedule(krates: &Krates) {
+    ///     for (i, level) in krates.levels().levels.into_iter().enumerate() {
+    ///         println!("wave {i}: {} crates", level.len());
+    ///     }
+    /// }
+    /// ```
+    pub fn levels(&self) -> Levels {
+        let crate_deps = self.crate_dependencies();
+
+        let mut cycle = vec![usize::MAX; self.krates_end];
+        for (i, krates) in Self::crate_cycles(&crate_deps).into_iter().enumerate() {
+            for nid in krates {
+                cycle[nid.index()] = i;
+            }
+        }
+
+        // Only whether each dependency is a dev-dependency matters here
+        let mut deps: Vec<BTreeMap<NodeId, bool>> = crate_deps
+            .into_iter()
+            .map(|deps| {
+                deps.into_iter()
+                    .map(|(dependency, edges)| {
+                        let dev_only = edges.iter().all(|(_, kind)| *kind == DepKind::Dev);
+                        (dependency, dev_only)
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut broken = Vec::new();
+
+        // Ignore the dev-dependencies between crates in the same cycle
+        for (dependent, dependencies) in deps.iter_mut().enumerate() {
+            if cycle[dependent] == usize::MAX {
+                continue;
+            }
+
+            dependencies.retain(|dependency, dev_only| {
+                let keep = !*dev_only || cycle[dependency.index()] != cycle[dependent];
+                if !keep {
+                    broken.push(BrokenDependency {
+                        dependent: NodeId::new(dependent),
+                        dependency: *dependency,
+                        dev_only: true,
+                    });
+                }
+                keep
+            });
+        }
+
+        let mut dependents = vec![Vec::new(); self.krates_end];
+        for (dependent, dependencies) in deps.iter().enumerate() {
+            for dependency in dependencies.keys() {
+                dependents[dependency.index()].push(NodeId::new(dependent));
+            }
+        }
+
+        let mut remaining: Vec<_> = deps.iter().map(|deps| deps.len()).collect();
+        let mut placed = vec![false; self.krates_end];
+        let mut placed_count = 0;
+        let mut levels = Vec::new();
+
+        let mut level: Vec<_> = (0..self.krates_end)
+            .filter(|i| remaining[*i] == 0)
+            .map(NodeId::new)
+            .collect();
+
+        while placed_count < self.krates_end {
+            if level.is_empty() {
+                // Only possible if there is a cycle that cargo itself wouldn't
+                // allow, so just break it so that every crate is placed
+                let Some(index) = (0..self.krates_end).find(|i| !placed[*i]) else {
+                    break;
+                };
+
+                for (dependency, dev_only) in &deps[index] {
+                    if !placed[dependency.index()] {
+                        broken.push(BrokenDependency {
+                            dependent: NodeId::new(index),
+                            dependency: *dependency,
+                            dev_only: *dev_only,
+                        });
+                    }
+                }
+
+                remaining[index] = 0;
+                level.push(NodeId::new(index));
+            }
+
+            let mut next = Vec::new();
+            for nid in &level {
+                placed[nid.index()] = true;
+            }
+            placed_count += level.len();
+
+            for nid in &level {
+                for dependent in &dependents[nid.index()] {
+                    let index = dependent.index();
+                    if placed[index] || remaining[index] == 0 {
+                        continue;
+                    }
+
+                    remaining[index] -= 1;
+                    if remaining[index] == 0 {
+                        next.push(*dependent);
+                    }
+                }
+            }
+
+            next.sort();
+            levels.push(std::mem::replace(&mut level, next));
+        }
+
+        broken.sort();
+
+        Levels { levels, broken }
+    }
+}
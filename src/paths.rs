@@ -0,0 +1,399 @@
+use crate::{EdgeId, Krates, Node, NodeId};
+use petgraph::{Direction, visit::EdgeRef};
+use std::collections::{BTreeMap, VecDeque};
+
+/// A path through the graph from a workspace member to a crate
+pub struct DependencyPath<'krates, E> {
+    /// The workspace member the path starts at
+    pub root: NodeId,
+    /// Each hop from one crate to another, in order. The dependency of the last
+    /// hop is the crate the path leads to, and there are no hops if that crate
+    /// is the workspace member itself
+    pub hops: Vec<PathHop<'krates, E>>,
+}
+
+/// A single hop in a [`DependencyPath`] from a crate to one of its dependencies
+pub struct PathHop<'krates, E> {
+    /// The crate that depends on [`Self::dependency`]
+    pub dependent: NodeId,
+    /// The crate being depended upon
+    pub dependency: NodeId,
+    /// The edge that links the dependent, or one of its features, with the
+    /// dependency or one of its features
+    pub edge_id: EdgeId,
+    /// The edge weight
+    pub edge: &'krates E,
+    /// The feature nodes of the dependency the path passes through, in order,
+    /// after taking the edge and before the next hop
+    pub features: Vec<NodeId>,
+}
+
+/// A way to leave the crate being walked, found by [`Krates::crossings`]
+struct Crossing {
+    /// The feature nodes walked through before the edge
+    route: Vec<NodeId>,
+    /// The edge to the other crate
+    edge: EdgeId,
+    /// The node in the other crate the edge leads to
+    target: NodeId,
+}
+
+impl<N, E> Krates<N, E> {
+    /// Gets the crate that a crate or feature node belongs to
+    #[inline]
+    pub(crate) fn owner(&self, nid: NodeId) -> NodeId {
+        match &self.graph[nid] {
+            Node::Krate { .. } => nid,
+            Node::Feature { krate_index, .. } => *krate_index,
+        }
+    }
+
+    #[inline]
+    fn root_ids(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.workspace_members
+            .iter()
+            .filter_map(|kid| self.nid_for_kid(kid))
+    }
+
+    /// Gets the path from a workspace member to the specified crate with the
+    /// fewest crate to crate hops, answering the question of why a crate is in
+    /// the graph at all.
+    ///
+    /// Returns `None` if the node is not a crate, or can't be reached from any
+    /// workspace member.
+    ///
+    /// ```no_run
+    /// use krates::{Edge, Krates, Kid};
+    ///
+    /// fn why(krates: &Krates, kid: &Kid) {
+    ///     let path = krates.shortest_path_to(krates.nid_for_kid(kid).unwrap()).unwrap();
+    ///
+    ///     print!("{}", krates[path.root].name);
+    ///     for hop in path.hops {
+    ///         match hop.edge {
+    ///             Edge::Dep { kind, .. } | Edge::DepFeature { kind, .. } => {
+    ///                 print!(" -({kind})-> {}", krates[hop.dependency].name);
+    ///             }
+    ///             Edge::Feature => print!(" -> {}", krates[hop.dependency].name),
+    ///         }
+    ///     }
+    ///     println!();
+    /// }
+    /// ```
+    pub fn shortest_path_to(&self, nid: NodeId) -> Option<DependencyPath<'_, E>> {
+        if nid.index() >= self.krates_end {
+            return None;
+        }
+
+        // Edges between the features of a single crate don't count as a hop,
+        // so this is a 0-1 BFS where those edges are explored first
+        let node_count = self.graph.node_count();
+        let mut cost = vec![usize::MAX; node_count];
+        let mut done = vec![false; node_count];
+        let mut parent: Vec<Option<EdgeId>> = vec![None; node_count];
+        let mut queue = VecDeque::new();
+
+        for root in self.root_ids() {
+            cost[root.index()] = 0;
+            queue.push_back(root);
+        }
+
+        while let Some(current) = queue.pop_front() {
+            if std::mem::replace(&mut done[current.index()], true) {
+                continue;
+            }
+
+            if current == nid {
+                break;
+            }
+
+            let owner = self.owner(current);
+            for edge in self.graph.edges_directed(current, Direction::Outgoing) {
+                let target = edge.target();
+                let hop = usize::from(self.owner(target) != owner);
+                let target_cost = cost[current.index()] + hop;
+
+                if target_cost < cost[target.index()] {
+                    cost[target.index()] = target_cost;
+                    parent[target.index()] = Some(edge.id());
+
+                    if hop == 0 {
+                        queue.push_front(target);
+                    } else {
+                        queue.push_back(target);
+                    }
+                }
+            }
+        }
+
+        if cost[nid.index()] == usize::MAX {
+            return None;
+        }
+
+        let mut edges = Vec::new();
+        let mut current = nid;
+        while let Some(eid) = parent[current.index()] {
+            edges.push(eid);
+            current = self.graph.edge_endpoints(eid)?.0;
+        }
+
+        let mut path = DependencyPath {
+            root: current,
+            hops: Vec::new(),
+        };
+
+        for eid in edges.into_iter().rev() {
+            let (source, target) = self.graph.edge_endpoints(eid)?;
+            let dependency = self.owner(target);
+
+            if self.owner(source) != dependency {
+                path.hops.push(PathHop {
+                    dependent: self.owner(source),
+                    dependency,
+                    edge_id: eid,
+                    edge: &self.graph[eid],
+                    features: Vec::new(),
+                });
+            }
+
+            if target != dependency {
+                if let Some(hop) = path.hops.last_mut() {
+                    hop.features.push(target);
+                }
+            }
+        }
+
+        Some(path)
+    }
+
+    /// Gets the paths from the workspace members to the specified crate, up to
+    /// the specified limit, similarly to `cargo tree --invert`.
+    ///
+    /// Each path visits a crate at most once, and when a crate can be reached
+    /// from its dependent via several edges, eg. both directly and via one of
+    /// its features, only one of them is used. Paths are not returned in any
+    /// particular order, use [`Self::shortest_path_to`] to get the shortest.
+    ///
+    /// ```no_run
+    /// use krates::{Krates, Kid};
+    ///
+    /// fn count_roots(krates: &Krates, kid: &Kid) -> usize {
+    ///     let paths = krates.paths_to(krates.nid_for_kid(kid).unwrap(), 1000);
+    ///     let mut roots: Vec<_> = paths.into_iter().map(|path| path.root).collect();
+    ///     roots.sort();
+    ///     roots.dedup();
+    ///     roots.len()
+    /// }
+    /// ```
+    pub fn paths_to(&self, nid: NodeId, limit: usize) -> Vec<DependencyPath<'_, E>> {
+        let mut paths = Vec::new();
+        if nid.index() >= self.krates_end || limit == 0 {
+            return paths;
+        }
+
+        // Only walk through nodes that can actually reach the crate, otherwise
+        // the search can explore large parts of the graph for nothing
+        let mut leads = vec![false; self.graph.node_count()];
+        let mut stack = vec![nid];
+        leads[nid.index()] = true;
+
+        while let Some(current) = stack.pop() {
+            for source in self.graph.neighbors_directed(current, Direction::Incoming) {
+                if !std::mem::replace(&mut leads[source.index()], true) {
+                    stack.push(source);
+                }
+            }
+        }
+
+        let mut on_path = vec![false; self.krates_end];
+        let mut hops = Vec::new();
+
+        for root in self.root_ids() {
+            if !leads[root.index()] {
+                continue;
+            }
+
+            on_path[root.index()] = true;
+            self.walk_paths(
+                root,
+                root,
+                nid,
+                &leads,
+                &mut on_path,
+                &mut hops,
+                &mut paths,
+                limit,
+            );
+            on_path[root.index()] = false;
+
+            if paths.len() >= limit {
+                break;
+            }
+        }
+
+        paths
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn walk_paths<'k>(
+        &'k self,
+        root: NodeId,
+        entry: NodeId,
+        target: NodeId,
+        leads: &[bool],
+        on_path: &mut [bool],
+        hops: &mut Vec<PathHop<'k, E>>,
+        paths: &mut Vec<DependencyPath<'k, E>>,
+        limit: usize,
+    ) {
+        let (to_target, crossings) = self.crossings(entry, target, leads, on_path);
+
+        if let Some(route) = to_target {
+            let mut path_hops: Vec<_> = hops
+                .iter()
+                .map(|hop| PathHop {
+                    dependent: hop.dependent,
+                    dependency: hop.dependency,
+                    edge_id: hop.edge_id,
+                    edge: hop.edge,
+                    features: hop.features.clone(),
+                })
+                .collect();
+
+            if let Some(hop) = path_hops.last_mut() {
+                hop.features.extend(route);
+            }
+
+            paths.push(DependencyPath {
+                root,
+                hops: path_hops,
+            });
+            return;
+        }
+
+        for crossing in crossings {
+            let dependent = self.owner(entry);
+            let dependency = self.owner(crossing.target);
+            let features_len = hops.last().map_or(0, |hop| hop.features.len());
+
+            if let Some(hop) = hops.last_mut() {
+                hop.features.extend(crossing.route);
+            }
+
+            hops.push(PathHop {
+                dependent,
+                dependency,
+                edge_id: crossing.edge,
+                edge: &self.graph[crossing.edge],
+                features: if crossing.target != dependency {
+                    vec![crossing.target]
+                } else {
+                    Vec::new()
+                },
+            });
+            on_path[dependency.index()] = true;
+
+            self.walk_paths(
+                root,
+                crossing.target,
+                target,
+                leads,
+                on_path,
+                hops,
+                paths,
+                limit,
+            );
+
+            on_path[dependency.index()] = false;
+            hops.pop();
+            if let Some(hop) = hops.last_mut() {
+                hop.features.truncate(features_len);
+            }
+
+            if paths.len() >= limit {
+                return;
+            }
+        }
+    }
+
+    /// Walks the features of the crate the entry node belongs to, returning
+    /// the route to the target if this is the target crate, otherwise the
+    /// edges that lead to other crates that haven't been visited yet, with
+    /// only one edge per crate
+    fn crossings(
+        &self,
+        entry: NodeId,
+        target: NodeId,
+        leads: &[bool],
+        on_path: &[bool],
+    ) -> (Option<Vec<NodeId>>, Vec<Crossing>) {
+        let owner = self.owner(entry);
+        let mut parents = BTreeMap::new();
+        let mut queue = VecDeque::from([entry]);
+        parents.insert(entry, None);
+
+        let route = |parents: &BTreeMap<NodeId, Option<NodeId>>, mut nid: NodeId| {
+            let mut route = Vec::new();
+            while let Some(Some(parent)) = parents.get(&nid) {
+                if nid.index() >= self.krates_end {
+                    route.push(nid);
+                }
+                nid = *parent;
+            }
+            route.reverse();
+            route
+        };
+
+        let mut crossings = BTreeMap::new();
+
+        while let Some(current) = queue.pop_front() {
+            if current == target {
+                return (Some(route(&parents, current)), Vec::new());
+            }
+
+            for edge in self.graph.edges_directed(current, Direction::Outgoing) {
+                let next = edge.target();
+                if !leads[next.index()] {
+                    continue;
+                }
+
+                let dependency = self.owner(next);
+                if dependency == owner {
+                    if let std::collections::btree_map::Entry::Vacant(parent) = parents.entry(next)
+                    {
+                        parent.insert(Some(current));
+                        queue.push_back(next);
+                    }
+                } else if !on_path[dependency.index()] {
+                    // Prefer the crossing with the shortest route, and within
+                    // that, an edge directly to the crate rather than a feature
+                    let route = route(&parents, current);
+                    let replace = match crossings.get(&dependency) {
+                        Some(Crossing {
+                            route: existing,
+                            target,
+                            ..
+                        }) => {
+                            (route.len(), next != dependency)
+                                < (existing.len(), *target != dependency)
+                        }
+                        None => true,
+                    };
+
+                    if replace {
+                        crossings.insert(
+                            dependency,
+                            Crossing {
+                                route,
+                                edge: edge.id(),
+                                target: next,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        (None, crossings.into_values().collect())
+    }
+}
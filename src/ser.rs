@@ -0,0 +1,766 @@
+//! Provides serialization and deserialization of a built [`Krates`] graph, so
+//! that it can be computed once and loaded many times without needing to
+//! rebuild it from metadata.
+//!
+//! The format is versioned, and loading validates that the graph upholds the
+//! same invariants as a graph created by the [`crate::Builder`], eg. that the
+//! crates are sorted by their id.
+
+use crate::{DepDeclaration, DepKind, Edge, EnabledFeatures, Kid, Krates, Node, UnitFeatures};
+use serde::{
+    de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor},
+    ser::{Serialize, SerializeMap, SerializeSeq, SerializeTuple, Serializer},
+};
+use std::{fmt, marker::PhantomData, sync::Arc};
+
+/// The current version of the serialized format, bumped whenever the format
+/// changes in an incompatible way
+const FORMAT_VERSION: u32 = 1;
+
+type Key<'de> = std::borrow::Cow<'de, str>;
+
+impl Serialize for Kid {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // The components are serialized as well, as the id is not guaranteed
+        // to parse the same if it has already been normalized
+        let mut tup = serializer.serialize_tuple(2)?;
+        tup.serialize_element(&self.repr)?;
+        tup.serialize_element(&self.components)?;
+        tup.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Kid {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let (repr, components) = <(String, [(usize, usize); 3])>::deserialize(deserializer)?;
+
+        for (start, end) in components {
+            if start > end
+                || end > repr.len()
+                || !repr.is_char_boundary(start)
+                || !repr.is_char_boundary(end)
+            {
+                return Err(de::Error::custom(format_args!(
+                    "invalid component range {start}..{end} for package id '{repr}'"
+                )));
+            }
+        }
+
+        Ok(Self { repr, components })
+    }
+}
+
+impl Serialize for DepKind {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(match self {
+            Self::Normal => "normal",
+            Self::Dev => "dev",
+            Self::Build => "build",
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for DepKind {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let kind = Key::deserialize(deserializer)?;
+        Ok(match kind.as_ref() {
+            "normal" => Self::Normal,
+            "dev" => Self::Dev,
+            "build" => Self::Build,
+            unknown => {
+                return Err(de::Error::unknown_variant(
+                    unknown,
+                    &["normal", "dev", "build"],
+                ));
+            }
+        })
+    }
+}
+
+impl Serialize for DepDeclaration {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("index", &self.index)?;
+        map.serialize_entry("req", &self.req)?;
+        map.serialize_entry("rename", &self.rename)?;
+        map.serialize_entry("optional", &self.optional)?;
+        map.serialize_entry("uses_default_features", &self.uses_default_features)?;
+        map.serialize_entry("features", &self.features)?;
+        map.serialize_entry("registry", &self.registry)?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for DepDeclaration {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct V;
+
+        impl<'de> Visitor<'de> for V {
+            type Value = DepDeclaration;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a dependency declaration")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut index = None;
+                let mut req = None;
+                let mut rename = None;
+                let mut optional = false;
+                let mut uses_default_features = true;
+                let mut features = Vec::new();
+                let mut registry = None;
+
+                while let Some(key) = map.next_key::<Key<'de>>()? {
+                    match key.as_ref() {
+                        "index" => index = Some(map.next_value()?),
+                        "req" => req = Some(map.next_value()?),
+                        "rename" => rename = map.next_value()?,
+                        "optional" => optional = map.next_value()?,
+                        "uses_default_features" => uses_default_features = map.next_value()?,
+                        "features" => features = map.next_value()?,
+                        "registry" => registry = map.next_value()?,
+                        _ => {
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                Ok(DepDeclaration {
+                    index: index.ok_or_else(|| de::Error::missing_field("index"))?,
+                    req: req.ok_or_else(|| de::Error::missing_field("req"))?,
+                    rename,
+                    optional,
+                    uses_default_features,
+                    features,
+                    registry,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(V)
+    }
+}
+
+impl Serialize for Edge {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+
+        match self {
+            Self::Feature => {
+                map.serialize_entry("type", "feature")?;
+            }
+            Self::Dep {
+                kind,
+                cfg,
+                declaration,
+            }
+            | Self::DepFeature {
+                kind,
+                cfg,
+                declaration,
+            } => {
+                map.serialize_entry(
+                    "type",
+                    if matches!(self, Self::Dep { .. }) {
+                        "dep"
+                    } else {
+                        "dep_feature"
+                    },
+                )?;
+                map.serialize_entry("kind", kind)?;
+                map.serialize_entry("cfg", cfg)?;
+                map.serialize_entry("declaration", &**declaration)?;
+            }
+        }
+
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Edge {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct V;
+
+        impl<'de> Visitor<'de> for V {
+            type Value = Edge;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("an edge")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut ty = None;
+                let mut kind = DepKind::Normal;
+                let mut cfg = None;
+                let mut declaration = None;
+
+                while let Some(key) = map.next_key::<Key<'de>>()? {
+                    match key.as_ref() {
+                        "type" => ty = Some(map.next_value::<String>()?),
+                        "kind" => kind = map.next_value()?,
+                        "cfg" => cfg = map.next_value()?,
+                        "declaration" => {
+                            declaration = Some(Arc::new(map.next_value::<DepDeclaration>()?));
+                        }
+                        _ => {
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                let ty = ty.ok_or_else(|| de::Error::missing_field("type"))?;
+                if ty == "feature" {
+                    return Ok(Edge::Feature);
+                }
+
+                let declaration =
+                    declaration.ok_or_else(|| de::Error::missing_field("declaration"))?;
+
+                match ty.as_str() {
+                    "dep" => Ok(Edge::Dep {
+                        kind,
+                        cfg,
+                        declaration,
+                    }),
+                    "dep_feature" => Ok(Edge::DepFeature {
+                        kind,
+                        cfg,
+                        declaration,
+                    }),
+                    unknown => Err(de::Error::unknown_variant(
+                        unknown,
+                        &["dep", "feature", "dep_feature"],
+                    )),
+                }
+            }
+        }
+
+        deserializer.deserialize_map(V)
+    }
+}
+
+impl Serialize for UnitFeatures {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("target", &self.target)?;
+        map.serialize_entry("host", &self.host)?;
+        map.serialize_entry("dev", &self.dev)?;
+        map.serialize_entry("dev_host", &self.dev_host)?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for UnitFeatures {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct V;
+
+        impl<'de> Visitor<'de> for V {
+            type Value = UnitFeatures;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("the features for each unit")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut uf = UnitFeatures::default();
+
+                while let Some(key) = map.next_key::<Key<'de>>()? {
+                    match key.as_ref() {
+                        "target" => uf.target = map.next_value()?,
+                        "host" => uf.host = map.next_value()?,
+                        "dev" => uf.dev = map.next_value()?,
+                        "dev_host" => uf.dev_host = map.next_value()?,
+                        _ => {
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                Ok(uf)
+            }
+        }
+
+        deserializer.deserialize_map(V)
+    }
+}
+
+/// The crate nodes, which are always first in the graph
+struct CrateNodes<'k, N>(&'k [petgraph::graph::Node<Node<N>>]);
+
+impl<N: Serialize> Serialize for CrateNodes<'_, N> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+
+        for node in self.0 {
+            let Node::Krate {
+                id,
+                krate,
+                features,
+                unit_features,
+                dep_mapping,
+                depth,
+            } = &node.weight
+            else {
+                unreachable!("crate nodes are always first in the graph");
+            };
+
+            seq.serialize_element(&CrateNode {
+                id,
+                krate,
+                features,
+                unit_features: unit_features.as_deref(),
+                dep_mapping,
+                depth: *depth,
+            })?;
+        }
+
+        seq.end()
+    }
+}
+
+struct CrateNode<'k, N> {
+    id: &'k Kid,
+    krate: &'k N,
+    features: &'k EnabledFeatures,
+    unit_features: Option<&'k UnitFeatures>,
+    dep_mapping: &'k [Option<crate::NodeId>],
+    depth: usize,
+}
+
+impl<N: Serialize> Serialize for CrateNode<'_, N> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("id", self.id)?;
+        map.serialize_entry("krate", self.krate)?;
+        map.serialize_entry("features", self.features)?;
+        map.serialize_entry("unit_features", &self.unit_features)?;
+        map.serialize_entry(
+            "dep_mapping",
+            &DepMapping(
+                self.dep_mapping
+                    .iter()
+                    .map(|nid| nid.map(|nid| nid.index())),
+            ),
+        )?;
+        map.serialize_entry("depth", &self.depth)?;
+        map.end()
+    }
+}
+
+struct DepMapping<I>(I);
+
+impl<I> Serialize for DepMapping<I>
+where
+    I: Iterator<Item = Option<usize>> + Clone,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.0.clone())
+    }
+}
+
+/// The feature nodes, serialized as the index of the crate they belong to
+/// and the name of the feature
+struct FeatureNodes<'k, N>(&'k [petgraph::graph::Node<Node<N>>]);
+
+impl<N> Serialize for FeatureNodes<'_, N> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.0.iter().map(|node| {
+            let Node::Feature { krate_index, name } = &node.weight else {
+                unreachable!("feature nodes are always after the crate nodes");
+            };
+
+            (krate_index.index(), name)
+        }))
+    }
+}
+
+/// The edges, serialized as the source and target node index and the weight
+struct Edges<'k, E>(&'k [petgraph::graph::Edge<E>]);
+
+impl<E: Serialize> Serialize for Edges<'_, E> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(
+            self.0
+                .iter()
+                .map(|edge| (edge.source().index(), edge.target().index(), &edge.weight)),
+        )
+    }
+}
+
+impl<N, E> Serialize for Krates<N, E>
+where
+    N: Serialize,
+    E: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let nodes = self.graph.raw_nodes();
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("version", &FORMAT_VERSION)?;
+        map.serialize_entry("workspace_root", &self.workspace_root)?;
+        map.serialize_entry("workspace_members", &self.workspace_members)?;
+        map.serialize_entry("no_default_roots", &self.no_default_roots)?;
+        map.serialize_entry("crates", &CrateNodes(&nodes[..self.krates_end]))?;
+        map.serialize_entry("features", &FeatureNodes(&nodes[self.krates_end..]))?;
+        map.serialize_entry("edges", &Edges(self.graph.raw_edges()))?;
+        map.end()
+    }
+}
+
+/// A deserialized crate node, which is validated before being added to the graph
+struct DeCrateNode<N> {
+    id: Kid,
+    krate: N,
+    features: EnabledFeatures,
+    unit_features: Option<UnitFeatures>,
+    dep_mapping: Vec<Option<usize>>,
+    depth: usize,
+}
+
+impl<'de, N> Deserialize<'de> for DeCrateNode<N>
+where
+    N: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct V<N>(PhantomData<N>);
+
+        impl<'de, N> Visitor<'de> for V<N>
+        where
+            N: Deserialize<'de>,
+        {
+            type Value = DeCrateNode<N>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a crate node")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut id = None;
+                let mut krate = None;
+                let mut features = EnabledFeatures::new();
+                let mut unit_features = None;
+                let mut dep_mapping = Vec::new();
+                let mut depth = 0;
+
+                while let Some(key) = map.next_key::<Key<'de>>()? {
+                    match key.as_ref() {
+                        "id" => id = Some(map.next_value()?),
+                        "krate" => krate = Some(map.next_value()?),
+                        "features" => features = map.next_value()?,
+                        "unit_features" => unit_features = map.next_value()?,
+                        "dep_mapping" => dep_mapping = map.next_value()?,
+                        "depth" => depth = map.next_value()?,
+                        _ => {
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                Ok(DeCrateNode {
+                    id: id.ok_or_else(|| de::Error::missing_field("id"))?,
+                    krate: krate.ok_or_else(|| de::Error::missing_field("krate"))?,
+                    features,
+                    unit_features,
+                    dep_mapping,
+                    depth,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(V(PhantomData))
+    }
+}
+
+/// Wrapper so that a sequence of items can be deserialized without knowing
+/// the concrete sequence type up front
+struct Items<T>(Vec<T>);
+
+impl<'de, T> Deserialize<'de> for Items<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct V<T>(PhantomData<T>);
+
+        impl<'de, T> Visitor<'de> for V<T>
+        where
+            T: Deserialize<'de>,
+        {
+            type Value = Items<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a sequence")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let mut items = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+                while let Some(item) = seq.next_element()? {
+                    items.push(item);
+                }
+                Ok(Items(items))
+            }
+        }
+
+        deserializer.deserialize_seq(V(PhantomData))
+    }
+}
+
+impl<'de, N, E> Deserialize<'de> for Krates<N, E>
+where
+    N: Deserialize<'de>,
+    E: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct V<N, E>(PhantomData<(N, E)>);
+
+        impl<'de, N, E> Visitor<'de> for V<N, E>
+        where
+            N: Deserialize<'de>,
+            E: Deserialize<'de>,
+        {
+            type Value = Krates<N, E>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a crate graph")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut version = None;
+                let mut workspace_root = None;
+                let mut workspace_members = None;
+                let mut no_default_roots = None;
+                let mut crates = None;
+                let mut features = None;
+                let mut edges = None;
+
+                while let Some(key) = map.next_key::<Key<'de>>()? {
+                    match key.as_ref() {
+                        "version" => {
+                            let v: u32 = map.next_value()?;
+                            if v != FORMAT_VERSION {
+                                return Err(de::Error::custom(format_args!(
+                                    "unsupported format version {v}, expected {FORMAT_VERSION}"
+                                )));
+                            }
+                            version = Some(v);
+                        }
+                        "workspace_root" => workspace_root = Some(map.next_value()?),
+                        "workspace_members" => {
+                            workspace_members = Some(map.next_value::<Vec<Kid>>()?);
+                        }
+                        "no_default_roots" => {
+                            no_default_roots = Some(map.next_value::<Vec<Kid>>()?);
+                        }
+                        "crates" => crates = Some(map.next_value::<Items<DeCrateNode<N>>>()?.0),
+                        "features" => {
+                            features = Some(map.next_value::<Vec<(usize, String)>>()?);
+                        }
+                        "edges" => edges = Some(map.next_value::<Items<(usize, usize, E)>>()?.0),
+                        _ => {
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                version.ok_or_else(|| de::Error::missing_field("version"))?;
+                let workspace_root =
+                    workspace_root.ok_or_else(|| de::Error::missing_field("workspace_root"))?;
+                let workspace_members = workspace_members
+                    .ok_or_else(|| de::Error::missing_field("workspace_members"))?;
+                let no_default_roots =
+                    no_default_roots.ok_or_else(|| de::Error::missing_field("no_default_roots"))?;
+                let crates = crates.ok_or_else(|| de::Error::missing_field("crates"))?;
+                let features = features.ok_or_else(|| de::Error::missing_field("features"))?;
+                let edges = edges.ok_or_else(|| de::Error::missing_field("edges"))?;
+
+                // Crates and workspace members are looked up via binary search
+                if let Some(w) = crates.windows(2).find(|w| w[0].id >= w[1].id) {
+                    return Err(de::Error::custom(format_args!(
+                        "crates are not sorted, '{}' is not before '{}'",
+                        w[0].id, w[1].id
+                    )));
+                }
+
+                if let Some(w) = workspace_members.windows(2).find(|w| w[0] >= w[1]) {
+                    return Err(de::Error::custom(format_args!(
+                        "workspace members are not sorted, '{}' is not before '{}'",
+                        w[0], w[1]
+                    )));
+                }
+
+                if let Some(wm) = workspace_members
+                    .iter()
+                    .find(|wm| crates.binary_search_by(|krate| krate.id.cmp(wm)).is_err())
+                {
+                    return Err(de::Error::custom(format_args!(
+                        "workspace member '{wm}' is not a crate in the graph"
+                    )));
+                }
+
+                if let Some(w) = no_default_roots.windows(2).find(|w| w[0] >= w[1]) {
+                    return Err(de::Error::custom(format_args!(
+                        "roots without default features are not sorted, '{}' is not before '{}'",
+                        w[0], w[1]
+                    )));
+                }
+
+                if let Some(root) = no_default_roots
+                    .iter()
+                    .find(|root| crates.binary_search_by(|krate| krate.id.cmp(root)).is_err())
+                {
+                    return Err(de::Error::custom(format_args!(
+                        "root without default features '{root}' is not a crate in the graph"
+                    )));
+                }
+
+                let krates_end = crates.len();
+                let node_count = krates_end + features.len();
+
+                let mut graph = petgraph::Graph::with_capacity(node_count, edges.len());
+
+                for krate in crates {
+                    if let Some(nid) = krate
+                        .dep_mapping
+                        .iter()
+                        .flatten()
+                        .find(|nid| **nid >= krates_end)
+                    {
+                        return Err(de::Error::custom(format_args!(
+                            "dependency of '{}' maps to node {nid}, which is not a crate",
+                            krate.id
+                        )));
+                    }
+
+                    graph.add_node(Node::Krate {
+                        id: krate.id,
+                        krate: krate.krate,
+                        features: krate.features,
+                        unit_features: krate.unit_features.map(Box::new),
+                        dep_mapping: krate
+                            .dep_mapping
+                            .into_iter()
+                            .map(|nid| nid.map(crate::NodeId::new))
+                            .collect(),
+                        depth: krate.depth,
+                    });
+                }
+
+                for (krate_index, name) in features {
+                    if krate_index >= krates_end {
+                        return Err(de::Error::custom(format_args!(
+                            "feature '{name}' belongs to node {krate_index}, which is not a crate"
+                        )));
+                    }
+
+                    graph.add_node(Node::Feature {
+                        krate_index: crate::NodeId::new(krate_index),
+                        name,
+                    });
+                }
+
+                for (source, target, weight) in edges {
+                    if source >= node_count || target >= node_count {
+                        return Err(de::Error::custom(format_args!(
+                            "edge {source} -> {target} refers to a node that does not exist"
+                        )));
+                    }
+
+                    graph.add_edge(
+                        crate::NodeId::new(source),
+                        crate::NodeId::new(target),
+                        weight,
+                    );
+                }
+
+                let lookups = crate::Lookups::new(&graph, krates_end);
+
+                Ok(Krates {
+                    graph,
+                    workspace_members,
+                    workspace_root,
+                    no_default_roots,
+                    krates_end,
+                    lookups,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(V(PhantomData))
+    }
+}
@@ -0,0 +1,502 @@
+use crate::{
+    DepKind, Edge, Error, Feature, Krates, Node, NodeId, ParsedFeature, Scope, UnitFeatures,
+    units::{self, DepEdge, SubFeature, Unit, UnitGraph},
+};
+use petgraph::visit::EdgeRef;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// Options for [`Krates::subgraph`]
+#[derive(Default)]
+pub struct SubgraphOptions {
+    ignore_kinds: Vec<(DepKind, Scope)>,
+}
+
+impl SubgraphOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ignores a specific dependency kind in the given scope, the same as
+    /// [`Builder::ignore_kind`](crate::Builder::ignore_kind), except the
+    /// workspace is the roots of the subgraph.
+    ///
+    /// ```
+    /// # use krates::{DepKind, Scope, SubgraphOptions};
+    /// SubgraphOptions::new().ignore_kind(DepKind::Dev, Scope::All);
+    /// ```
+    pub fn ignore_kind(&mut self, kind: DepKind, scope: Scope) -> &mut Self {
+        self.ignore_kinds.push((kind, scope));
+        self
+    }
+
+    #[inline]
+    fn ignores(&self, kind: DepKind, is_root: bool) -> bool {
+        self.ignore_kinds.iter().any(|(ik, scope)| {
+            *ik == kind
+                && match scope {
+                    Scope::Workspace => is_root,
+                    Scope::NonWorkspace => !is_root,
+                    Scope::All => true,
+                }
+        })
+    }
+}
+
+/// An edge from a crate to one of its dependencies that isn't ignored
+#[derive(Clone)]
+struct IndexedEdge {
+    id: usize,
+    kind: DepKind,
+    optional: bool,
+    /// The features the dependency declaration enables
+    features: Vec<String>,
+}
+
+/// Resolves the features of the crates in a graph again, where only the
+/// features that are already enabled in the graph can be enabled
+struct Resolver<'k> {
+    krates: &'k Krates<crate::Package, Edge>,
+    /// The edges of each crate, indexed by dependency, so that visiting a
+    /// dependency doesn't need to scan every edge of the crate
+    edges: &'k [BTreeMap<usize, Vec<IndexedEdge>>],
+    /// True if crates built for the host are resolved separately
+    units: bool,
+}
+
+impl UnitGraph for Resolver<'_> {
+    type Feature = String;
+
+    fn is_proc_macro(&self, krate: usize) -> bool {
+        matches!(&self.krates.graph[NodeId::new(krate)], Node::Krate { krate, .. } if krate.targets.iter().any(|t| t.is_proc_macro()))
+    }
+
+    fn dependencies(&self, krate: usize) -> impl Iterator<Item = usize> {
+        self.edges[krate].keys().copied()
+    }
+
+    fn edges(&self, krate: usize, dep: usize) -> impl Iterator<Item = DepEdge<'_, String>> {
+        self.edges[krate]
+            .get(&dep)
+            .into_iter()
+            .flatten()
+            .map(|edge| DepEdge {
+                id: edge.id,
+                kind: edge.kind,
+                optional: edge.optional,
+                features: &edge.features,
+            })
+    }
+
+    fn sub_features(
+        &self,
+        krate: usize,
+        feature: &String,
+    ) -> Option<impl Iterator<Item = SubFeature<String>>> {
+        let nid = NodeId::new(krate);
+        let Node::Krate {
+            krate: pkg,
+            features: enabled,
+            ..
+        } = &self.krates.graph[nid]
+        else {
+            return None;
+        };
+
+        if !enabled.contains(feature) {
+            return None;
+        }
+
+        let sub_features = pkg.features.get(feature).into_iter().flatten();
+        Some(sub_features.flat_map(move |sf| {
+            let (simple, dep) = match ParsedFeature::from(sf.as_str()).feat() {
+                Feature::Simple(feat) => (Some(feat), None),
+                Feature::Krate(name) => (None, Some((name, None, true))),
+                Feature::Strong { krate, feature } => (None, Some((krate, Some(feature), true))),
+                Feature::Weak { krate, feature } => (None, Some((krate, Some(feature), false))),
+            };
+
+            let deps = dep.into_iter().flat_map(move |(name, feature, strong)| {
+                self.krates
+                    .named_dependencies(nid, name)
+                    .into_iter()
+                    .map(move |dep| {
+                        // The builder links a renamed dependency enabled by a
+                        // feature to a feature of the same name on the
+                        // dependency, but only in the unified graph
+                        let feature = feature.or_else(|| {
+                            let renamed = matches!(&self.krates.graph[dep], Node::Krate { krate, .. } if krate.name != name);
+                            (!self.units && renamed).then_some(name)
+                        });
+
+                        SubFeature::Dep {
+                            krate: dep.index(),
+                            feature: feature.map(str::to_owned),
+                            strong,
+                        }
+                    })
+            });
+
+            simple
+                .map(|feat| SubFeature::Feature(feat.to_owned()))
+                .into_iter()
+                .chain(deps)
+        }))
+    }
+}
+
+impl Krates<crate::Package, Edge> {
+    /// Creates a new graph that only contains the crates and features that
+    /// are reachable from the specified roots, which become the workspace
+    /// members of the new graph. Node ids that are not crates are ignored.
+    ///
+    /// Each root keeps the features it has enabled in this graph, and the
+    /// features of every other crate, including which of its optional
+    /// dependencies are enabled, are resolved again from the roots, so that
+    /// features only enabled by crates that aren't in the new graph are not
+    /// present. If the graph was built with
+    /// [`Builder::unit_features`](crate::Builder::unit_features), the features
+    /// for each kind of compile unit are resolved again as well.
+    ///
+    /// The crates keep the same relative order so that lookups such as
+    /// [`Self::nid_for_kid`] work, but node and edge ids are different from
+    /// the ones in this graph.
+    ///
+    /// ```no_run
+    /// use krates::{DepKind, Krates, Scope, SubgraphOptions};
+    ///
+    /// fn product(krates: &Krates, name: &str) -> Krates {
+    ///     let root = krates.krates_by_name(name).next().unwrap().node_id;
+    ///
+    ///     krates
+    ///         .subgraph([root], SubgraphOptions::new().ignore_kind(DepKind::Dev, Scope::All))
+    ///         .unwrap()
+    /// }
+    /// ```
+    pub fn subgraph(
+        &self,
+        roots: impl IntoIterator<Item = NodeId>,
+        options: &SubgraphOptions,
+    ) -> Result<Self, Error> {
+        let node_count = self.graph.node_count();
+        let mut is_root = vec![false; self.krates_end];
+
+        for root in roots {
+            if root.index() < self.krates_end {
+                is_root[root.index()] = true;
+            }
+        }
+
+        if !is_root.iter().any(|r| *r) {
+            return Err(Error::NoRootKrates);
+        }
+
+        let enabled = |nid: NodeId| match &self.graph[nid] {
+            Node::Krate { features, .. } => features,
+            Node::Feature { .. } => unreachable!("roots are always crates"),
+        };
+
+        // The dependency edges of each crate that aren't ignored, which are
+        // the same no matter how the features are resolved
+        let mut dep_edges = vec![BTreeMap::<usize, Vec<IndexedEdge>>::new(); self.krates_end];
+        for edge in self.graph.edge_references() {
+            let (Edge::Dep {
+                kind, declaration, ..
+            }
+            | Edge::DepFeature {
+                kind, declaration, ..
+            }) = edge.weight()
+            else {
+                continue;
+            };
+
+            let source = edge.source().index();
+            if options.ignores(*kind, is_root[source]) {
+                continue;
+            }
+
+            dep_edges[source]
+                .entry(self.owner(edge.target()).index())
+                .or_default()
+                .push(IndexedEdge {
+                    id: edge.id().index(),
+                    kind: *kind,
+                    optional: declaration.optional,
+                    features: declaration
+                        .features
+                        .iter()
+                        .cloned()
+                        .chain(
+                            declaration
+                                .uses_default_features
+                                .then(|| "default".to_owned()),
+                        )
+                        .collect(),
+                });
+        }
+
+        let resolver = |units: bool| Resolver {
+            krates: self,
+            edges: &dep_edges,
+            units,
+        };
+        let roots = || (0..self.krates_end).filter(|index| is_root[*index]);
+
+        let mut followed = vec![false; self.graph.edge_count()];
+        let states = units::resolve(
+            &resolver(false),
+            roots(),
+            |root, _| enabled(NodeId::new(root)).clone(),
+            false,
+            true,
+            |edge| followed[edge] = true,
+        );
+
+        // The features for each kind of compile unit, if they were resolved
+        // when this graph was built
+        let mut unit_features = self.graph.raw_nodes()[..self.krates_end]
+            .iter()
+            .any(|node| {
+                matches!(
+                    &node.weight,
+                    Node::Krate {
+                        unit_features: Some(_),
+                        ..
+                    }
+                )
+            })
+            .then(|| {
+                let seeds = |dev: bool| {
+                    move |root: usize, unit: Unit| {
+                        let root = NodeId::new(root);
+                        let Node::Krate {
+                            features,
+                            unit_features: Some(uf),
+                            ..
+                        } = &self.graph[root]
+                        else {
+                            return enabled(root).clone();
+                        };
+
+                        match (unit, dev) {
+                            (Unit::Target, false) => uf.target.as_ref(),
+                            (Unit::Host, false) => uf.host.as_ref(),
+                            (Unit::Target, true) => uf.dev.as_ref(),
+                            (Unit::Host, true) => uf.dev_host.as_ref(),
+                        }
+                        .unwrap_or(features)
+                        .clone()
+                    }
+                };
+
+                let mut unit_features = vec![UnitFeatures::default(); self.krates_end];
+                for dev in [false, true] {
+                    let states =
+                        units::resolve(&resolver(true), roots(), seeds(dev), true, dev, |_| {});
+                    for ((index, unit), state) in states {
+                        if !state.visited {
+                            continue;
+                        }
+
+                        let uf = &mut unit_features[index];
+                        match (unit, dev) {
+                            (Unit::Target, false) => uf.target = Some(state.features),
+                            (Unit::Host, false) => uf.host = Some(state.features),
+                            (Unit::Target, true) => uf.dev = Some(state.features),
+                            (Unit::Host, true) => uf.dev_host = Some(state.features),
+                        }
+                    }
+                }
+
+                unit_features
+            });
+
+        let mut features = vec![None; self.krates_end];
+        let mut dep_features = vec![BTreeMap::new(); self.krates_end];
+        for ((index, _), state) in states {
+            if state.visited {
+                features[index] = Some(state.features);
+                dep_features[index] = state.dep_features;
+            }
+        }
+
+        // The dependencies each crate actually links to
+        let mut dependencies = vec![BTreeSet::new(); self.krates_end];
+        for edge in self.graph.edge_references() {
+            if followed[edge.id().index()] {
+                dependencies[edge.source().index()].insert(self.owner(edge.target()));
+            }
+        }
+
+        let mut kept = vec![false; node_count];
+        for (index, node) in self.graph.raw_nodes().iter().enumerate() {
+            kept[index] = match &node.weight {
+                Node::Krate { .. } => features[index].is_some(),
+                Node::Feature { krate_index, name } => features[krate_index.index()]
+                    .as_ref()
+                    .is_some_and(|features| features.contains(name)),
+            };
+        }
+
+        let kept_edges: Vec<_> = self
+            .graph
+            .edge_references()
+            .map(|edge| {
+                let (source, target) = (edge.source(), edge.target());
+                if !kept[source.index()] || !kept[target.index()] {
+                    return false;
+                }
+
+                let dependency = self.owner(target);
+                match (edge.weight(), &self.graph[source]) {
+                    (Edge::Dep { .. } | Edge::DepFeature { .. }, _) => followed[edge.id().index()],
+                    // A feature enabling another feature of the same crate, or
+                    // of a dependency, which only applies if it's linked
+                    (Edge::Feature, Node::Feature { krate_index, .. }) => {
+                        dependency == *krate_index
+                            || dependencies[krate_index.index()].contains(&dependency)
+                    }
+                    // The feature of a dependency enabled by one of the crate's
+                    // features, which may no longer be enabled
+                    (Edge::Feature, Node::Krate { .. }) => {
+                        let Node::Feature { name, .. } = &self.graph[target] else {
+                            return false;
+                        };
+
+                        dependencies[source.index()].contains(&dependency)
+                            && dep_features[source.index()]
+                                .get(&dependency.index())
+                                .is_some_and(|features| features.contains(name))
+                    }
+                }
+            })
+            .collect();
+
+        // Map the old node ids to the new ones, keeping the same order
+        let mut new_ids = vec![None; node_count];
+        let mut next = 0;
+        for (index, new_id) in new_ids.iter_mut().enumerate() {
+            if kept[index] {
+                *new_id = Some(NodeId::new(next));
+                next += 1;
+            }
+        }
+
+        let krates_end = kept[..self.krates_end].iter().filter(|k| **k).count();
+
+        // The minimum number of dependency edges from any of the roots
+        let mut depths = vec![usize::MAX; self.krates_end];
+        let mut queue: VecDeque<_> = (0..self.krates_end)
+            .filter(|index| is_root[*index])
+            .map(|index| (index, 0))
+            .collect();
+
+        while let Some((index, depth)) = queue.pop_front() {
+            if depths[index] != usize::MAX {
+                continue;
+            }
+
+            depths[index] = depth;
+            queue.extend(
+                dependencies[index]
+                    .iter()
+                    .filter(|dep| depths[dep.index()] == usize::MAX)
+                    .map(|dep| (dep.index(), depth + 1)),
+            );
+        }
+
+        let mut graph = petgraph::Graph::with_capacity(next, 0);
+        let mut workspace_members = Vec::new();
+
+        for (index, node) in self.graph.raw_nodes().iter().enumerate() {
+            if !kept[index] {
+                continue;
+            }
+
+            let node = match &node.weight {
+                Node::Krate {
+                    id,
+                    krate,
+                    dep_mapping,
+                    ..
+                } => {
+                    if is_root[index] {
+                        workspace_members.push(id.clone());
+                    }
+
+                    Node::Krate {
+                        id: id.clone(),
+                        krate: krate.clone(),
+                        features: features[index].take().unwrap_or_default(),
+                        unit_features: unit_features
+                            .as_mut()
+                            .map(|uf| Box::new(std::mem::take(&mut uf[index]))),
+                        dep_mapping: dep_mapping
+                            .iter()
+                            .map(|dep| dep.and_then(|dep| new_ids[dep.index()]))
+                            .collect(),
+                        depth: depths[index],
+                    }
+                }
+                Node::Feature { krate_index, name } => Node::Feature {
+                    krate_index: new_ids[krate_index.index()].expect("feature's crate is kept"),
+                    name: name.clone(),
+                },
+            };
+
+            graph.add_node(node);
+        }
+
+        for (index, edge) in self.graph.raw_edges().iter().enumerate() {
+            if !kept_edges[index] {
+                continue;
+            }
+
+            if let (Some(source), Some(target)) = (
+                new_ids[edge.source().index()],
+                new_ids[edge.target().index()],
+            ) {
+                graph.add_edge(source, target, edge.weight.clone());
+            }
+        }
+
+        let lookups = crate::Lookups::new(&graph, krates_end);
+
+        let no_default_roots = self
+            .no_default_roots
+            .iter()
+            .filter(|kid| {
+                self.nid_for_kid(kid)
+                    .is_some_and(|nid| is_root[nid.index()])
+            })
+            .cloned()
+            .collect();
+
+        Ok(Krates {
+            graph,
+            workspace_members,
+            workspace_root: self.workspace_root.clone(),
+            no_default_roots,
+            krates_end,
+            lookups,
+        })
+    }
+
+    /// Gets the crates a dependency name used in the crate's features refers to
+    fn named_dependencies(&self, krate: NodeId, name: &str) -> BTreeSet<NodeId> {
+        let Node::Krate {
+            krate: pkg,
+            dep_mapping,
+            ..
+        } = &self.graph[krate]
+        else {
+            return BTreeSet::new();
+        };
+
+        pkg.dependencies
+            .iter()
+            .zip(dep_mapping)
+            .filter(|(dep, _)| dep.rename.as_deref().unwrap_or(&dep.name) == name)
+            .filter_map(|(_, nid)| *nid)
+            .collect()
+    }
+}
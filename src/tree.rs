@@ -0,0 +1,217 @@
+use crate::{EdgeId, Krates, Node, NodeId};
+use petgraph::{Direction, visit::EdgeRef};
+
+/// A crate that depends on another crate in a [`ReverseTree`]
+pub struct ReverseDependent<'krates, N> {
+    /// The dependent crate
+    pub krate: &'krates N,
+    /// The dependent crate's node id
+    pub node_id: NodeId,
+    /// The crate this crate depends on, which is the parent in the tree
+    pub dependency: NodeId,
+    /// The edge that links the crate, or one of its features, with the dependency
+    pub edge_id: EdgeId,
+    /// The depth in the tree, the direct dependents of the root are at depth 1
+    pub depth: usize,
+    /// True if the dependents of this crate are not walked, as they were
+    /// already walked earlier in the tree with deduplication enabled, or it is
+    /// already one of the crates it depends on, ie. a cycle
+    pub duplicate: bool,
+}
+
+type EdgeFilter<'krates, E> = Box<dyn FnMut(&E) -> bool + 'krates>;
+
+struct Frame {
+    dependents: Vec<(NodeId, EdgeId)>,
+    next: usize,
+}
+
+/// A lazy, depth first, traversal of the crates that transitively depend on a
+/// crate, similarly to `cargo tree --invert`, created via [`Krates::reverse_tree`]
+pub struct ReverseTree<'krates, N, E> {
+    krates: &'krates Krates<N, E>,
+    root: Option<NodeId>,
+    stack: Vec<(NodeId, Frame)>,
+    on_path: Vec<bool>,
+    /// The depth each crate's dependents were walked at
+    expanded: Vec<Option<usize>>,
+    dedup: bool,
+    max_depth: usize,
+    edge_filter: Option<EdgeFilter<'krates, E>>,
+}
+
+impl<'krates, N, E> ReverseTree<'krates, N, E> {
+    /// If true, a crate whose dependents were already walked is still yielded,
+    /// but marked as a [`ReverseDependent::duplicate`] and its dependents are
+    /// not walked again. Defaults to false.
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Only walks dependents up to the specified depth, where the direct
+    /// dependents of the root are at depth 1
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Only walks edges that match the filter, eg. to skip dev dependencies or
+    /// dependencies that only apply to certain targets. A crate is a dependent
+    /// if any of its edges to the dependency match.
+    ///
+    /// ```no_run
+    /// use krates::{DepKind, Edge, Krates, NodeId};
+    ///
+    /// fn normal_dependents(krates: &Krates, nid: NodeId) -> usize {
+    ///     krates
+    ///         .reverse_tree(nid)
+    ///         .edge_filter(|edge| match edge {
+    ///             Edge::Dep { kind, cfg, .. } | Edge::DepFeature { kind, cfg, .. } => {
+    ///                 *kind == DepKind::Normal && cfg.is_none()
+    ///             }
+    ///             Edge::Feature => true,
+    ///         })
+    ///         .count()
+    /// }
+    /// ```
+    pub fn edge_filter(mut self, filter: impl FnMut(&E) -> bool + 'krates) -> Self {
+        self.edge_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Gets the crates that depend directly on the crate, skipping over the
+    /// crate's feature nodes
+    fn frame(&mut self, nid: NodeId) -> Frame {
+        let graph = &self.krates.graph;
+        let mut dependents: Vec<(NodeId, EdgeId)> = Vec::new();
+
+        let features = graph
+            .neighbors_directed(nid, Direction::Incoming)
+            .filter(|source| {
+                matches!(&graph[*source], Node::Feature { krate_index, .. } if *krate_index == nid)
+            });
+
+        for target in std::iter::once(nid).chain(features) {
+            for edge in graph.edges_directed(target, Direction::Incoming) {
+                if !matches!(&graph[edge.source()], Node::Krate { .. })
+                    || edge.source() == nid
+                    || dependents.iter().any(|(dep, _)| *dep == edge.source())
+                {
+                    continue;
+                }
+
+                if let Some(filter) = &mut self.edge_filter {
+                    if !filter(edge.weight()) {
+                        continue;
+                    }
+                }
+
+                dependents.push((edge.source(), edge.id()));
+            }
+        }
+
+        dependents.sort_by_key(|(dep, _)| *dep);
+
+        Frame {
+            dependents,
+            next: 0,
+        }
+    }
+}
+
+impl<'krates, N, E> Iterator for ReverseTree<'krates, N, E> {
+    type Item = ReverseDependent<'krates, N>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(root) = self.root.take() {
+            let frame = self.frame(root);
+            self.on_path[root.index()] = true;
+            self.expanded[root.index()] = Some(0);
+            self.stack.push((root, frame));
+        }
+
+        loop {
+            let depth = self.stack.len();
+            if depth > self.max_depth {
+                self.stack.clear();
+                return None;
+            }
+
+            let (dependency, frame) = self.stack.last_mut()?;
+            let dependency = *dependency;
+
+            let Some((node_id, edge_id)) = frame.dependents.get(frame.next).copied() else {
+                self.stack.pop();
+                self.on_path[dependency.index()] = false;
+                continue;
+            };
+            frame.next += 1;
+
+            let index = node_id.index();
+            // A crate only counts as walked once its dependents are, and if
+            // there is a max depth, only if they were walked from at least as
+            // close to the root, as they may have been cut off by the max depth
+            let walked =
+                self.expanded[index].is_some_and(|at| at <= depth || self.max_depth == usize::MAX);
+            let duplicate = self.on_path[index] || (self.dedup && walked);
+
+            if !duplicate && depth < self.max_depth {
+                let frame = self.frame(node_id);
+                self.on_path[index] = true;
+                self.expanded[index] = Some(depth);
+                self.stack.push((node_id, frame));
+            }
+
+            let Node::Krate { krate, .. } = &self.krates.graph[node_id] else {
+                unreachable!("dependents are always crates");
+            };
+
+            return Some(ReverseDependent {
+                krate,
+                node_id,
+                dependency,
+                edge_id,
+                depth,
+                duplicate,
+            });
+        }
+    }
+}
+
+impl<N, E> Krates<N, E> {
+    /// Walks the crates that transitively depend on the specified crate,
+    /// skipping over feature nodes the same way [`Self::direct_dependents`]
+    /// does. Each crate is yielded before its own dependents, and a crate is
+    /// never walked again while walking its own dependents.
+    ///
+    /// ```no_run
+    /// use krates::{Krates, NodeId};
+    ///
+    /// fn print_tree(krates: &Krates, nid: NodeId) {
+    ///     println!("{} {}", krates[nid].name, krates[nid].version);
+    ///     for dependent in krates.reverse_tree(nid).dedup(true) {
+    ///         println!(
+    ///             "{:width$}{} {}{}",
+    ///             "",
+    ///             dependent.krate.name,
+    ///             dependent.krate.version,
+    ///             if dependent.duplicate { " (*)" } else { "" },
+    ///             width = dependent.depth * 4,
+    ///         );
+    ///     }
+    /// }
+    /// ```
+    pub fn reverse_tree(&self, nid: NodeId) -> ReverseTree<'_, N, E> {
+        ReverseTree {
+            krates: self,
+            root: (nid.index() < self.krates_end).then_some(nid),
+            stack: Vec::new(),
+            on_path: vec![false; self.krates_end],
+            expanded: vec![None; self.krates_end],
+            dedup: false,
+            max_depth: usize::MAX,
+            edge_filter: None,
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Resolves the features enabled on crates for each kind of compile unit they
+//! are built as, which is shared by the [`crate::Builder`], which resolves
+//! them from the metadata, and [`crate::Krates::subgraph`], which resolves
+//! them again from a graph that has already been built.
+
+use crate::DepKind;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The kind of compile unit a crate is built as, see [`crate::UnitFeatures`]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Unit {
+    Target,
+    Host,
+}
+
+pub(crate) struct UnitState<F> {
+    /// True if the crate is built for the unit
+    pub(crate) visited: bool,
+    pub(crate) features: BTreeSet<F>,
+    /// The optional dependencies enabled by one of the crate's features
+    pub(crate) activated: BTreeSet<usize>,
+    /// The features enabled on dependencies via `krate/feat` or `krate?/feat`
+    pub(crate) dep_features: BTreeMap<usize, BTreeSet<F>>,
+}
+
+impl<F> Default for UnitState<F> {
+    fn default() -> Self {
+        Self {
+            visited: false,
+            features: BTreeSet::new(),
+            activated: BTreeSet::new(),
+            dep_features: BTreeMap::new(),
+        }
+    }
+}
+
+/// Something enabled by one of a crate's features
+pub(crate) enum SubFeature<F> {
+    /// Another feature of the same crate
+    Feature(F),
+    /// A dependency, and optionally one of its features. Weak, ie.
+    /// `krate?/feature`, dependencies are only enabled by something else
+    Dep {
+        krate: usize,
+        feature: Option<F>,
+        strong: bool,
+    },
+}
+
+/// An edge from a crate to one of its dependencies
+pub(crate) struct DepEdge<'g, F> {
+    /// The id of the edge, reported once it is followed
+    pub(crate) id: usize,
+    pub(crate) kind: DepKind,
+    pub(crate) optional: bool,
+    /// The features the edge enables on the dependency
+    pub(crate) features: &'g [F],
+}
+
+/// The crates, features, and dependency edges to resolve, with crates
+/// identified by their index
+pub(crate) trait UnitGraph {
+    type Feature: Clone + Ord;
+
+    fn is_proc_macro(&self, krate: usize) -> bool;
+
+    /// The crates the crate has at least one edge to
+    fn dependencies(&self, krate: usize) -> impl Iterator<Item = usize>;
+
+    /// The edges from the crate to one of its dependencies
+    fn edges(&self, krate: usize, dep: usize) -> impl Iterator<Item = DepEdge<'_, Self::Feature>>;
+
+    /// What a feature of the crate enables, or `None` if the feature can't be
+    /// enabled at all
+    fn sub_features(
+        &self,
+        krate: usize,
+        feature: &Self::Feature,
+    ) -> Option<impl Iterator<Item = SubFeature<Self::Feature>>>;
+}
+
+enum Visit<F> {
+    Krate(usize, Unit),
+    Feature(usize, Unit, F),
+    Dep(usize, Unit, usize),
+}
+
+/// Resolves the features of every crate reachable from the roots, the same as
+/// cargo's v2 feature resolver.
+///
+/// If `units` is false, crates built for the host are not resolved separately,
+/// which gives the unified view that `cargo metadata` gives. Dev-dependencies
+/// are only followed if `dev` is true, and `followed` is called with the id of
+/// every edge that is followed.
+pub(crate) fn resolve<G: UnitGraph, I: IntoIterator<Item = G::Feature>>(
+    graph: &G,
+    roots: impl IntoIterator<Item = usize>,
+    seeds: impl Fn(usize, Unit) -> I,
+    units: bool,
+    dev: bool,
+    mut followed: impl FnMut(usize),
+) -> BTreeMap<(usize, Unit), UnitState<G::Feature>> {
+    let mut states = BTreeMap::<(usize, Unit), UnitState<G::Feature>>::new();
+    let mut stack = Vec::new();
+
+    for root in roots {
+        let unit = if units && graph.is_proc_macro(root) {
+            Unit::Host
+        } else {
+            Unit::Target
+        };
+
+        stack.push(Visit::Krate(root, unit));
+        stack.extend(
+            seeds(root, unit)
+                .into_iter()
+                .map(|feat| Visit::Feature(root, unit, feat)),
+        );
+    }
+
+    while let Some(visit) = stack.pop() {
+        match visit {
+            Visit::Krate(krate, unit) => {
+                let state = states.entry((krate, unit)).or_default();
+                if std::mem::replace(&mut state.visited, true) {
+                    continue;
+                }
+
+                stack.extend(
+                    graph
+                        .dependencies(krate)
+                        .map(|dep| Visit::Dep(krate, unit, dep)),
+                );
+            }
+            Visit::Feature(krate, unit, feat) => {
+                let Some(sub_features) = graph.sub_features(krate, &feat) else {
+                    continue;
+                };
+
+                let state = states.entry((krate, unit)).or_default();
+                if !state.features.insert(feat.clone()) {
+                    continue;
+                }
+
+                stack.push(Visit::Krate(krate, unit));
+
+                for sf in sub_features {
+                    match sf {
+                        SubFeature::Feature(feat) => {
+                            stack.push(Visit::Feature(krate, unit, feat));
+                        }
+                        SubFeature::Dep {
+                            krate: dep,
+                            feature,
+                            strong,
+                        } => {
+                            if strong {
+                                state.activated.insert(dep);
+                            }
+
+                            if let Some(feature) = feature {
+                                state.dep_features.entry(dep).or_default().insert(feature);
+                            }
+
+                            stack.push(Visit::Dep(krate, unit, dep));
+                        }
+                    }
+                }
+            }
+            Visit::Dep(krate, unit, dep) => {
+                // Edges are only followed once the crate itself is known to
+                // be built for the unit, at which point every dependency is
+                // visited again
+                let Some(state) = states.get(&(krate, unit)).filter(|state| state.visited) else {
+                    continue;
+                };
+
+                let is_activated = state.activated.contains(&dep);
+                let dep_features = state.dep_features.get(&dep);
+                let dep_is_proc_macro = graph.is_proc_macro(dep);
+
+                for edge in graph.edges(krate, dep) {
+                    if (edge.kind == DepKind::Dev && !dev) || (edge.optional && !is_activated) {
+                        continue;
+                    }
+
+                    followed(edge.id);
+
+                    let dep_unit = if units && (edge.kind == DepKind::Build || dep_is_proc_macro) {
+                        Unit::Host
+                    } else {
+                        unit
+                    };
+
+                    stack.push(Visit::Krate(dep, dep_unit));
+                    stack.extend(
+                        edge.features
+                            .iter()
+                            .chain(dep_features.into_iter().flatten())
+                            .map(|feat| Visit::Feature(dep, dep_unit, feat.clone())),
+                    );
+                }
+            }
+        }
+    }
+
+    states
+}
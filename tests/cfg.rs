@@ -65,3 +65,217 @@ fn handles_non_builtin() {
 
     ktest::assert_dotgraph!("all-features.json", kb);
 }
+
+/// Ensures custom cfgs can be used to match dependencies gated behind them
+#[test]
+fn matches_custom_cfgs() {
+    use krates::Cfg;
+
+    assert_eq!(
+        "tokio_unstable".parse::<Cfg>().unwrap(),
+        Cfg::Flag("tokio_unstable".to_owned())
+    );
+    assert_eq!(
+        "docsrs=\"yes\"".parse::<Cfg>().unwrap(),
+        Cfg::KeyValue {
+            key: "docsrs".to_owned(),
+            value: "yes".to_owned()
+        }
+    );
+    assert!("docsrs=yes".parse::<Cfg>().is_err());
+    assert!("not a flag".parse::<Cfg>().is_err());
+
+    // Gate b -> wasm-bindgen-futures behind custom cfgs rather than a target
+    let contents = std::fs::read_to_string("tests/all-features.json")
+        .unwrap()
+        .replace(
+            r#"cfg(all(target_vendor = \"xboxone\"))"#,
+            r#"cfg(all(tokio_unstable, docsrs = \"yes\", feature = \"x\"))"#,
+        );
+
+    let has_futures = |cfgs: &[&str]| -> bool {
+        let md: krates::cm::Metadata = serde_json::from_str(&contents).unwrap();
+
+        let mut kb = krates::Builder::new();
+        kb.include_targets_with_cfgs(std::iter::once((
+            "x86_64-unknown-linux-gnu",
+            vec![],
+            cfgs.iter().map(|cfg| cfg.parse().unwrap()).collect(),
+        )));
+
+        let krates: krates::Krates = kb.build_with_metadata(md, krates::NoneFilter).unwrap();
+        krates
+            .krates_by_name("wasm-bindgen-futures")
+            .next()
+            .is_some()
+    };
+
+    assert!(!has_futures(&[]));
+    assert!(!has_futures(&["tokio_unstable", "docsrs=\"yes\""]));
+    assert!(!has_futures(&[
+        "tokio_unstable",
+        "docsrs=\"no\"",
+        "feature=\"x\""
+    ]));
+    assert!(has_futures(&[
+        "tokio_unstable",
+        "docsrs=\"yes\"",
+        "feature=\"x\""
+    ]));
+}
+
+/// Ensures that targets created from `rustc --print cfg` output or target specs
+/// evaluate `cfg()` expressions the same as builtin targets
+#[test]
+fn handles_custom_targets() {
+    let build = |target: krates::Target| -> std::collections::BTreeSet<String> {
+        let contents = std::fs::read_to_string("tests/all-features.json").unwrap();
+        let md: krates::cm::Metadata = serde_json::from_str(&contents).unwrap();
+
+        let mut kb = krates::Builder::new();
+        kb.include_targets(std::iter::once((target, vec![])));
+
+        let krates: krates::Krates = kb.build_with_metadata(md, krates::NoneFilter).unwrap();
+        krates.krates().map(|k| k.id.repr.clone()).collect()
+    };
+
+    // rustc --print cfg --target x86_64-unknown-linux-gnu
+    let cfg = r#"debug_assertions
+panic="unwind"
+target_abi=""
+target_arch="x86_64"
+target_endian="little"
+target_env="gnu"
+target_family="unix"
+target_feature="fxsr"
+target_feature="sse"
+target_feature="sse2"
+target_has_atomic="16"
+target_has_atomic="32"
+target_has_atomic="64"
+target_has_atomic="8"
+target_has_atomic="ptr"
+target_os="linux"
+target_pointer_width="64"
+target_vendor="unknown"
+unix
+"#;
+
+    let spec = r#"{
+    "arch": "x86_64",
+    "cpu": "x86-64",
+    "crt-static-respected": true,
+    "data-layout": "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-i128:128-f80:128-n8:16:32:64-S128",
+    "dynamic-linking": true,
+    "env": "gnu",
+    "has-rpath": true,
+    "llvm-target": "x86_64-unknown-linux-gnu",
+    "max-atomic-width": 64,
+    "os": "linux",
+    "plt-by-default": false,
+    "position-independent-executables": true,
+    "target-family": [
+        "unix"
+    ],
+    "target-pointer-width": "64"
+}"#;
+
+    let builtin = build("x86_64-unknown-linux-gnu".into());
+
+    assert_eq!(
+        build(krates::Target::from_target_spec("x86_64-unknown-linux-gnu", spec).unwrap()),
+        builtin
+    );
+    assert_eq!(
+        build(krates::Target::from_rustc_cfg("x86_64-unknown-linux-gnu", cfg).unwrap()),
+        builtin
+    );
+
+    // The only difference when using a different name is that c's dependency
+    // on nix (and nix's own dependencies) is only for the x86_64-unknown-linux-gnu triple
+    let renamed = build(krates::Target::from_rustc_cfg("x86_64-custom-linux-gnu", cfg).unwrap());
+    assert!(renamed.is_subset(&builtin));
+    assert_eq!(
+        builtin
+            .difference(&renamed)
+            .filter_map(|k| k.split(' ').next())
+            .collect::<Vec<_>>(),
+        ["bitflags", "cfg-if", "nix", "void"]
+    );
+
+    // Unknown targets can't evaluate any cfg() expressions, note that this
+    // triple needs to be unparseable, otherwise the `targets` feature would
+    // turn it into a (partially) known target
+    let unknown_target = krates::Target::from("custom-target");
+    assert!(matches!(unknown_target, krates::Target::Unknown(_)));
+    let unknown = build(unknown_target);
+    assert!(!unknown.iter().any(|k| k.starts_with("libc ")));
+    assert!(renamed.iter().any(|k| k.starts_with("libc ")));
+
+    assert!(matches!(
+        krates::Target::from_rustc_cfg("nope", "target_os=\"linux\""),
+        Err(krates::Error::InvalidTarget(_))
+    ));
+    assert!(matches!(
+        krates::Target::from_target_spec("nope", "{\"arch\": 32}"),
+        Err(krates::Error::InvalidTarget(_))
+    ));
+}
+
+/// Ensures the user is informed when a `target_feature` decides whether a
+/// dependency is included
+#[test]
+fn reports_target_feature_decisions() {
+    use krates::Diagnostic;
+    use std::sync::{Arc, Mutex};
+
+    let build = |triple: &str, features: Vec<String>| -> Vec<Diagnostic> {
+        let contents = std::fs::read_to_string("tests/all-features.json").unwrap();
+        let md: krates::cm::Metadata = serde_json::from_str(&contents).unwrap();
+
+        let diags = Arc::new(Mutex::new(Vec::new()));
+
+        let mut kb = krates::Builder::new();
+        kb.include_targets(std::iter::once((triple, features)));
+        let d = diags.clone();
+        kb.on_diagnostic(move |diag| d.lock().unwrap().push(diag));
+
+        let _krates: krates::Krates = kb.build_with_metadata(md, krates::NoneFilter).unwrap();
+        std::mem::take(&mut *diags.lock().unwrap())
+    };
+
+    // The target_arch predicate fails regardless of the target_feature
+    assert!(build("x86_64-unknown-linux-gnu", vec![]).is_empty());
+
+    let find =
+        |diags: &[Diagnostic], dependent: &str, dependency: &str| -> Option<(String, bool)> {
+            diags.iter().find_map(|diag| {
+                let Diagnostic::CfgDecidedEdge {
+                    dependent: dt,
+                    dependency: dc,
+                    expression,
+                    included,
+                } = diag
+                else {
+                    return None;
+                };
+
+                (dt.name() == dependent && dc.name() == dependency)
+                    .then(|| (expression.clone(), *included))
+            })
+        };
+
+    let expr = r#"cfg(all(target_arch = "wasm32", target_feature = "atomics"))"#.to_owned();
+
+    let excluded = build("wasm32-unknown-unknown", vec![]);
+    assert_eq!(
+        find(&excluded, "b", "wasm-bindgen-futures"),
+        Some((expr.clone(), false))
+    );
+
+    let included = build("wasm32-unknown-unknown", vec!["atomics".to_owned()]);
+    assert_eq!(
+        find(&included, "b", "wasm-bindgen-futures"),
+        Some((expr, true))
+    );
+}
@@ -0,0 +1,69 @@
+use ktest::util::{build_krates, dev_cycle_krates};
+
+/// Tests that cycles between crates are found, and that the traversals on the
+/// graph terminate when there are cycles
+#[test]
+fn finds_cycles() {
+    for fixture in ["all-features", "direct", "pid-stable"] {
+        let krates: krates::Krates =
+            build_krates(format!("{fixture}.json"), krates::Builder::new()).unwrap();
+
+        assert!(krates.cycles().is_empty());
+    }
+
+    let (krates, memchr, aho) = dev_cycle_krates();
+
+    let cycles = krates.cycles();
+    assert_eq!(cycles.len(), 1);
+
+    let cycle = &cycles[0];
+    let mut expected = vec![memchr, aho];
+    expected.sort();
+    assert_eq!(cycle.krates, expected);
+    assert!(cycle.is_dev_cycle());
+
+    let deps: Vec<_> = cycle
+        .dependencies
+        .iter()
+        .map(|dep| {
+            let (source, _) = krates.graph().edge_endpoints(dep.edge_id).unwrap();
+            assert_eq!(source, dep.dependent);
+            (dep.dependent, dep.dependency, dep.kinds.clone())
+        })
+        .collect();
+
+    let mut expected = vec![
+        (aho, memchr, vec![krates::DepKind::Normal]),
+        (memchr, aho, vec![krates::DepKind::Dev]),
+    ];
+    expected.sort_by_key(|(dependent, _, _)| *dependent);
+    assert_eq!(deps, expected);
+
+    // The traversals on the graph must handle the cycle
+    assert!(krates.reverse_tree(memchr).any(|rd| rd.node_id == aho));
+    assert!(krates.reverse_tree(aho).any(|rd| rd.node_id == memchr));
+    assert!(!krates.paths_to(aho, 100).is_empty());
+    assert!(krates.shortest_path_to(aho).is_some());
+    assert!(
+        krates
+            .direct_dependents(aho)
+            .iter()
+            .any(|dd| dd.node_id == memchr)
+    );
+
+    // Ignoring the dev-dependency that closes the cycle gives the same crates as
+    // the original graph
+    let original: krates::Krates =
+        build_krates("all-features.json", krates::Builder::new()).unwrap();
+
+    let ids = |krates: Vec<&krates::cm::Package>| {
+        krates
+            .into_iter()
+            .map(|pkg| pkg.id.repr.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        ids(krates.krates_filtered(krates::DepKind::Dev)),
+        ids(original.krates_filtered(krates::DepKind::Dev))
+    );
+}
@@ -0,0 +1,62 @@
+use ktest::util::build_krates;
+
+/// Tests that every dependency edge carries the declaration in the source
+/// crate's manifest it was resolved from, shared between all of its edges
+#[test]
+fn edge_declarations() {
+    use krates::petgraph::visit::EdgeRef;
+
+    let krates: krates::Krates = build_krates("all-features.json", krates::Builder::new()).unwrap();
+
+    let graph = krates.graph();
+    let mut checked = 0;
+    let mut shared = std::collections::BTreeMap::new();
+
+    for edge in graph.edge_references() {
+        let (krates::Edge::Dep { declaration, .. } | krates::Edge::DepFeature { declaration, .. }) =
+            edge.weight()
+        else {
+            continue;
+        };
+
+        // Every edge created from the same declaration shares it
+        let first = shared
+            .entry((edge.source(), declaration.index))
+            .or_insert_with(|| declaration.clone());
+        assert!(std::sync::Arc::ptr_eq(first, declaration));
+
+        let krates::Node::Krate { krate: src, .. } = &graph[edge.source()] else {
+            panic!("dependency edge from a feature");
+        };
+
+        let target = match &graph[edge.target()] {
+            krates::Node::Krate { .. } => edge.target(),
+            krates::Node::Feature { krate_index, .. } => *krate_index,
+        };
+
+        let declared = &src.dependencies[declaration.index];
+        assert_eq!(declaration.req, declared.req);
+        assert_eq!(declaration.rename, declared.rename);
+        assert_eq!(declaration.optional, declared.optional);
+        assert_eq!(
+            declaration.uses_default_features,
+            declared.uses_default_features
+        );
+        assert_eq!(declaration.features, declared.features);
+        assert_eq!(declaration.registry, declared.registry);
+
+        let resolved = &krates[target];
+        assert!(declaration.req.matches(&resolved.version) || declared.path.is_some());
+        assert_eq!(
+            krates
+                .resolved_dependency(edge.source(), declaration.index)
+                .map(|k| &k.id),
+            Some(&resolved.id)
+        );
+
+        checked += 1;
+    }
+
+    assert!(checked > 100);
+    assert!(shared.len() < checked);
+}
@@ -0,0 +1,100 @@
+use ktest::util::metadata;
+
+/// Tests that the depth of each crate is its minimum distance from the roots,
+/// and that crates beyond the maximum depth are filtered
+#[test]
+fn max_depth() {
+    use std::collections::BTreeMap;
+
+    let build = |max_depth: Option<usize>| -> (krates::Krates, Vec<String>) {
+        let md = metadata("direct.json").unwrap();
+
+        let mut kb = krates::Builder::new();
+        if let Some(max_depth) = max_depth {
+            kb.max_depth(max_depth);
+        }
+
+        let mut filtered = Vec::new();
+        let krates = kb
+            .build_with_metadata(md, |pkg: krates::cm::Package| filtered.push(pkg.id.repr))
+            .unwrap();
+        (krates, filtered)
+    };
+
+    let depths = |krates: &krates::Krates| -> BTreeMap<String, usize> {
+        krates
+            .graph()
+            .node_weights()
+            .filter_map(|node| match node {
+                krates::Node::Krate { id, depth, .. } => Some((id.repr.clone(), *depth)),
+                krates::Node::Feature { .. } => None,
+            })
+            .collect()
+    };
+
+    let (full, full_filtered) = build(None);
+    let full_depths = depths(&full);
+
+    let roots: Vec<_> = full_depths
+        .iter()
+        .filter(|(_, depth)| **depth == 0)
+        .collect();
+    assert_eq!(roots.len(), 1);
+    assert!(roots[0].0.starts_with("direct "));
+
+    // The depth of every crate is exactly one more than its closest dependent
+    let mut closest = BTreeMap::<String, usize>::new();
+    for node in full.graph().node_weights() {
+        let krates::Node::Krate {
+            id,
+            dep_mapping,
+            depth,
+            ..
+        } = node
+        else {
+            continue;
+        };
+
+        for dep in dep_mapping.iter().flatten() {
+            let dep_depth = full_depths[&full[*dep].id.repr];
+            assert!(dep_depth <= depth + 1, "{id} -> {}", full[*dep].id);
+
+            let closest = closest
+                .entry(full[*dep].id.repr.clone())
+                .or_insert(usize::MAX);
+            *closest = (*closest).min(depth + 1);
+        }
+    }
+
+    for (id, depth) in &full_depths {
+        if *depth > 0 {
+            assert_eq!(closest[id], *depth, "{id}");
+        }
+    }
+
+    let max = *full_depths.values().max().unwrap();
+    assert!(max > 2);
+
+    for max_depth in [0, 1, 2] {
+        let (limited, mut filtered) = build(Some(max_depth));
+        let expected: BTreeMap<_, _> = full_depths
+            .iter()
+            .filter(|(_, depth)| **depth <= max_depth)
+            .map(|(id, depth)| (id.clone(), *depth))
+            .collect();
+
+        assert_eq!(depths(&limited), expected);
+
+        // Every crate beyond the maximum depth is reported as filtered
+        let mut expected_filtered: Vec<_> = full_depths
+            .iter()
+            .filter(|(_, depth)| **depth > max_depth)
+            .map(|(id, _)| id.clone())
+            .chain(full_filtered.iter().cloned())
+            .collect();
+        expected_filtered.sort();
+        filtered.sort();
+
+        assert_eq!(filtered, expected_filtered);
+    }
+}
@@ -0,0 +1,126 @@
+use ktest::util::build_krates;
+
+/// Tests that the differences between two graphs are found
+#[test]
+fn diffs_graphs() {
+    use std::fmt::Write;
+
+    let build = |path: &str| -> krates::Krates {
+        // The second workspace has a root crate that doesn't depend on the
+        // other members, so use every member as a root
+        let mut kb = krates::Builder::new();
+        kb.workspace(true);
+        build_krates(path, kb).unwrap()
+    };
+
+    let old = build("all-features.json");
+    let new = build("all-features2.json");
+
+    let same = krates::diff(&old, &old);
+    assert!(same.added.is_empty());
+    assert!(same.removed.is_empty());
+    assert!(same.changed.is_empty());
+    assert!(same.features.is_empty());
+    assert!(same.dependencies.is_empty());
+
+    let diff = krates::diff(&old, &new);
+
+    let kid = |kid: &krates::Kid| {
+        let source = match kid.source_kind() {
+            krates::SourceKind::Registry => "",
+            krates::SourceKind::Git => " (git)",
+            krates::SourceKind::Path => " (path)",
+            krates::SourceKind::Other => " (other)",
+        };
+        format!("{} {}{source}", kid.name(), kid.version())
+    };
+
+    let mut out = String::new();
+    for added in &diff.added {
+        writeln!(out, "+ {}", kid(added)).unwrap();
+    }
+    for removed in &diff.removed {
+        writeln!(out, "- {}", kid(removed)).unwrap();
+    }
+    for change in &diff.changed {
+        writeln!(
+            out,
+            "~ {} -> {} {:?}{}",
+            kid(change.old),
+            kid(change.new),
+            change.version,
+            if change.source_changed {
+                " source changed"
+            } else {
+                ""
+            }
+        )
+        .unwrap();
+    }
+    for fc in &diff.features {
+        writeln!(out, "f {} +{:?} -{:?}", kid(fc.new), fc.added, fc.removed).unwrap();
+    }
+    for dc in &diff.dependencies {
+        writeln!(
+            out,
+            "d {:?} {} -> {} {}{}",
+            dc.change,
+            kid(dc.dependent),
+            kid(dc.dependency),
+            dc.kind,
+            dc.cfg.map(|cfg| format!(" '{cfg}'")).unwrap_or_default()
+        )
+        .unwrap();
+    }
+
+    ktest::assert_snapshot!(out);
+}
+
+/// Tests that dependencies on different versions of the same crate, which
+/// need to be renamed, are diffed separately
+#[test]
+fn diffs_renamed_dependencies() {
+    let build = |md: krates::cm::Metadata| -> krates::Krates {
+        krates::Builder::new()
+            .build_with_metadata(md, krates::NoneFilter)
+            .unwrap()
+    };
+
+    let old = build(ktest::util::metadata("pid-stable.json").unwrap());
+
+    // Remove the dependency on the old version of getrandom, keeping the one
+    // on the newer version
+    let contents = std::fs::read_to_string("tests/pid-stable.json").unwrap();
+    let mut md: serde_json::Value = serde_json::from_str(&contents).unwrap();
+
+    let pid = "path+file:///home/jake/code/krates/tests/pid#0.1.0";
+    for pkg in md["packages"].as_array_mut().unwrap() {
+        if pkg["id"] == pid {
+            pkg["dependencies"]
+                .as_array_mut()
+                .unwrap()
+                .retain(|dep| dep["rename"] != "getrandom_old");
+        }
+    }
+
+    for node in md["resolve"]["nodes"].as_array_mut().unwrap() {
+        if node["id"] == pid {
+            node["deps"]
+                .as_array_mut()
+                .unwrap()
+                .retain(|dep| dep["name"] != "getrandom_old");
+        }
+    }
+
+    let new = build(serde_json::from_value(md).unwrap());
+
+    let diff = krates::diff(&old, &new);
+
+    let getrandom: Vec<_> = diff
+        .dependencies
+        .iter()
+        .filter(|dc| dc.dependent.name() == "pid" && dc.dependency.name() == "getrandom")
+        .map(|dc| (dc.change, dc.dependency.version()))
+        .collect();
+    assert_eq!(getrandom, [(krates::Change::Removed, "0.1.16")]);
+}
@@ -0,0 +1,43 @@
+use ktest::util::build_krates;
+
+/// Tests that duplicate versions are grouped by name and source, with the
+/// crates that require each version
+#[test]
+fn groups_duplicates() {
+    use std::fmt::Write;
+
+    let krates: krates::Krates = build_krates("pid-stable.json", krates::Builder::new()).unwrap();
+
+    let mut dupes = String::new();
+    for dupe in krates.duplicates() {
+        writeln!(
+            dupes,
+            "{} {:?} {:?}",
+            dupe.name, dupe.source_kind, dupe.compatibility
+        )
+        .unwrap();
+
+        for version in dupe.versions {
+            assert_eq!(krates[version.node_id].id, version.krate.id);
+            writeln!(dupes, "  {}", version.krate.version).unwrap();
+
+            for dependent in version.dependents {
+                let reqs: Vec<_> = dependent
+                    .requirements
+                    .iter()
+                    .map(|req| req.to_string())
+                    .collect();
+                writeln!(
+                    dupes,
+                    "    {} {} ({})",
+                    dependent.krate.name,
+                    dependent.krate.version,
+                    reqs.join(", ")
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    ktest::assert_snapshot!(dupes);
+}
@@ -0,0 +1,150 @@
+use ktest::util::{build_krates, metadata};
+
+/// Tests that features are explained back to where they originated
+#[test]
+fn explains_features() {
+    let explain = |file: &str, krate: &str, version: &str, feature: &str| {
+        let krates: krates::Krates = build_krates(file, krates::Builder::new()).unwrap();
+
+        let graph = krates.graph();
+        let name = |nid: krates::NodeId| match &graph[nid] {
+            krates::Node::Krate { krate, .. } => format!("[{}]", krate.name),
+            krates::Node::Feature { krate_index, name } => {
+                format!("{}/{name}", krates[*krate_index].name)
+            }
+        };
+
+        let kid: krates::Kid = krates
+            .krates_by_name(krate)
+            .find(|km| km.krate.version.to_string() == version)
+            .unwrap()
+            .krate
+            .id
+            .clone()
+            .into();
+
+        assert!(krates.explain_feature(&kid, "not-a-feature").is_none());
+
+        krates
+            .explain_feature(&kid, feature)
+            .unwrap()
+            .into_iter()
+            .map(|chain| {
+                chain
+                    .activations
+                    .into_iter()
+                    .map(|act| {
+                        format!(
+                            "{} -{:?}-> {}",
+                            name(act.source),
+                            act.kind,
+                            name(act.target)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        explain("all-features.json", "c", "0.1.0", "leftpad"),
+        [
+            vec!["[b] -Default-> c/default", "c/default -Feature-> c/leftpad"],
+            vec![
+                "c/leftier-strings -Cli-> c/leftier-strings",
+                "c/leftier-strings -Feature-> c/leftpad"
+            ],
+        ]
+    );
+
+    assert_eq!(
+        explain("all-features.json", "memchr", "2.2.1", "use_std"),
+        [
+            vec![
+                "[weedle] -Default-> nom/default",
+                "nom/default -Feature-> nom/std",
+                "nom/std -Strong-> memchr/use_std",
+            ],
+            vec![
+                "[bindgen] -Default-> regex/default",
+                "regex/default -Feature-> regex/perf",
+                "regex/perf -Feature-> regex/perf-literal",
+                "regex/perf-literal -Feature-> regex/memchr",
+                "regex/memchr -Krate-> [memchr]",
+                "[regex] -Default-> memchr/default",
+                "memchr/default -Feature-> memchr/use_std",
+            ],
+            vec![
+                "[bindgen] -Default-> regex/default",
+                "regex/default -Feature-> regex/perf",
+                "regex/perf -Feature-> regex/perf-literal",
+                "regex/perf-literal -Feature-> regex/aho-corasick",
+                "regex/aho-corasick -Krate-> [aho-corasick]",
+                "[regex] -Default-> aho-corasick/default",
+                "aho-corasick/default -Feature-> aho-corasick/std",
+                "aho-corasick/std -Strong-> memchr/use_std",
+            ],
+        ]
+    );
+
+    // time-macros is enabled by time's `macros` feature, so `time-macros?/formatting`
+    // applies
+    assert_eq!(
+        explain("direct.json", "time-macros", "0.2.5", "formatting"),
+        [vec![
+            "[simple_asn1] -Dependency-> time/formatting",
+            "time/formatting -Weak-> time-macros/formatting"
+        ]]
+    );
+
+    // The root's `default` feature isn't enabled by the user, but is still
+    // the origin of the features it enables
+    assert_eq!(
+        explain("pid-opaque.json", "pid", "0.1.0", "default"),
+        [vec!["pid/default -RootDefault-> pid/default"]]
+    );
+
+    assert_eq!(
+        explain("pid-stable.json", "pid", "0.1.0", "time03"),
+        [vec![
+            "pid/default -RootDefault-> pid/default",
+            "pid/default -Feature-> pid/time03"
+        ]]
+    );
+}
+
+/// Tests that the root's `default` feature is only enabled by default if the
+/// user didn't override the root's features without it, and that graphs with
+/// only crates can't explain features
+#[test]
+fn explains_root_feature_overrides() {
+    let explain = |features: &[&str], default: bool, crates_only: bool| {
+        let md = metadata("pid-stable.json").unwrap();
+        let kid: krates::Kid = md.workspace_members[0].clone().into();
+
+        let mut kb = krates::Builder::new();
+        kb.root_features(kid.clone(), features.iter().copied(), default)
+            .crates_only(crates_only);
+
+        let krates: krates::Krates = kb.build_with_metadata(md, krates::NoneFilter).unwrap();
+
+        krates.explain_feature(&kid, "default").map(|chains| {
+            chains
+                .into_iter()
+                .flat_map(|chain| chain.activations)
+                .map(|act| act.kind)
+                .collect::<Vec<_>>()
+        })
+    };
+
+    assert_eq!(
+        explain(&[], true, false),
+        Some(vec![krates::ActivationKind::RootDefault])
+    );
+    assert_eq!(
+        explain(&["default"], false, false),
+        Some(vec![krates::ActivationKind::Cli])
+    );
+    assert_eq!(explain(&[], false, false), None);
+    assert_eq!(explain(&[], true, true), None);
+}
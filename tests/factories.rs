@@ -0,0 +1,123 @@
+use ktest::util::metadata;
+
+/// Tests that the node and edge factories are given the same nodes and edges,
+/// in the same order, as the default graph, with the context of each
+#[test]
+fn node_and_edge_factories() {
+    use krates::petgraph::visit::EdgeRef;
+
+    struct Crate {
+        id: String,
+        features: Vec<String>,
+        is_workspace_member: bool,
+        depth: usize,
+    }
+
+    struct Dep {
+        source: (String, Option<String>),
+        target: (String, Option<String>),
+        edge: krates::Edge,
+    }
+
+    let md = metadata("all-features.json").unwrap();
+
+    let krates: krates::Krates = krates::Builder::new()
+        .build_with_metadata(md.clone(), krates::NoneFilter)
+        .unwrap();
+
+    let custom: krates::Krates<Crate, Dep> = krates::Builder::new()
+        .build_with_metadata_and_factories(
+            md,
+            krates::NoneFilter,
+            |ctx: krates::NodeContext<'_>, pkg: krates::cm::Package| {
+                assert_eq!(ctx.id.repr, pkg.id.repr);
+
+                Crate {
+                    id: pkg.id.repr,
+                    features: ctx.features.iter().cloned().collect(),
+                    is_workspace_member: ctx.is_workspace_member,
+                    depth: ctx.depth,
+                }
+            },
+            |ctx: krates::EdgeContext<'_>, edge: krates::Edge| Dep {
+                source: (
+                    ctx.source.id.repr.clone(),
+                    ctx.source_feature.map(String::from),
+                ),
+                target: (
+                    ctx.target.id.repr.clone(),
+                    ctx.target_feature.map(String::from),
+                ),
+                edge,
+            },
+        )
+        .unwrap();
+
+    assert_eq!(krates.graph().node_count(), custom.graph().node_count());
+    assert_eq!(krates.graph().edge_count(), custom.graph().edge_count());
+
+    for (expected, actual) in krates
+        .graph()
+        .node_weights()
+        .zip(custom.graph().node_weights())
+    {
+        match (expected, actual) {
+            (
+                krates::Node::Krate {
+                    id,
+                    features,
+                    depth,
+                    ..
+                },
+                krates::Node::Krate { krate, .. },
+            ) => {
+                assert_eq!(id.repr, krate.id);
+                assert_eq!(features.iter().cloned().collect::<Vec<_>>(), krate.features);
+                assert_eq!(*depth, krate.depth);
+                assert_eq!(
+                    krates.workspace_members().any(|wm| {
+                        matches!(wm, krates::Node::Krate { id: wid, .. } if wid == id)
+                    }),
+                    krate.is_workspace_member
+                );
+            }
+            (
+                krates::Node::Feature { krate_index, name },
+                krates::Node::Feature {
+                    krate_index: ci,
+                    name: cn,
+                },
+            ) => {
+                assert_eq!(krate_index, ci);
+                assert_eq!(name, cn);
+            }
+            _ => panic!("node kinds differ"),
+        }
+    }
+
+    let describe = |nid: krates::NodeId| -> (String, Option<String>) {
+        match &krates.graph()[nid] {
+            krates::Node::Krate { id, .. } => (id.repr.clone(), None),
+            krates::Node::Feature { krate_index, name } => {
+                (krates[*krate_index].id.repr.clone(), Some(name.clone()))
+            }
+        }
+    };
+
+    for (expected, actual) in krates
+        .graph()
+        .edge_references()
+        .zip(custom.graph().edge_references())
+    {
+        assert_eq!(expected.source(), actual.source());
+        assert_eq!(expected.target(), actual.target());
+        assert_eq!(
+            expected.weight().to_string(),
+            actual.weight().edge.to_string()
+        );
+
+        let dep = actual.weight();
+        assert_eq!(describe(expected.source()), dep.source);
+        assert_eq!(describe(expected.target()), dep.target);
+    }
+}
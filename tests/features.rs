@@ -323,6 +323,247 @@ fn chained_optional_weak_features() {
     ktest::assert_dotgraph!(cmd);
 }
 
+/// Ensures that features are resolved separately for each kind of compile unit
+/// when requested, rather than being unified like `cargo metadata`
+#[test]
+fn resolves_unit_features() {
+    let build = |unit_features: bool| -> krates::Krates {
+        let contents = std::fs::read_to_string("tests/all-features.json").unwrap();
+        let md: krates::cm::Metadata = serde_json::from_str(&contents).unwrap();
+
+        let mut kb = krates::Builder::new();
+        kb.unit_features(unit_features);
+        kb.build_with_metadata(md, krates::NoneFilter).unwrap()
+    };
+
+    let krates = build(true);
+
+    let units = |name: &str, version: &str| {
+        let km = krates
+            .krates_by_name(name)
+            .find(|km| km.kid.version() == version)
+            .unwrap();
+        krates.get_unit_features(km.kid).unwrap().clone()
+    };
+
+    let set = |feats: &[&str]| -> Option<krates::EnabledFeatures> {
+        Some(feats.iter().map(|s| (*s).to_owned()).collect())
+    };
+
+    // `c` is a normal dependency of `b`, which enables more features than `a`
+    // does via its build dependency on it
+    let c = units("c", "0.1.0");
+    assert_eq!(
+        c.target,
+        set(&["default", "lazy_static", "leftier-strings", "leftpad"])
+    );
+    assert_eq!(c.host, set(&["default", "leftpad"]));
+
+    // Build dependencies are only ever built for the host
+    let cc = units("cc", "1.0.50");
+    assert_eq!(cc.target, None);
+    assert_eq!(cc.host, set(&[]));
+    assert_eq!(cc.dev, None);
+
+    // Dev dependencies are only built when building tests
+    let difference = units("difference", "2.0.0");
+    assert_eq!(difference.target, None);
+    assert_eq!(difference.host, None);
+    assert_eq!(difference.dev, set(&["default"]));
+
+    // The features enabled via the build dependency chain are not unified with
+    // the ones enabled via the dev dependency on `ring`
+    let winapi = units("winapi", "0.3.8");
+    assert_eq!(
+        winapi.host,
+        set(&["errhandlingapi", "libloaderapi", "winerror"])
+    );
+    assert_eq!(winapi.dev, set(&["ntsecapi", "wtypesbase"]));
+
+    // The unified features are the same regardless of unit resolution
+    let unified = build(false);
+    for km in unified.krates_by_name("winapi") {
+        assert_eq!(
+            unified.get_enabled_features(km.kid),
+            krates.get_enabled_features(km.kid)
+        );
+        assert!(unified.get_unit_features(km.kid).is_none());
+    }
+}
+
+/// Ensures that `krate?/feature` applies to each unit the dependency is built
+/// for, even if the dependency is only enabled via `dep:krate`
+#[test]
+fn resolves_unit_weak_features() {
+    let contents = std::fs::read_to_string("tests/direct.json").unwrap();
+    let md: krates::cm::Metadata = serde_json::from_str(&contents).unwrap();
+
+    let mut kb = krates::Builder::new();
+    kb.unit_features(true);
+    let krates: krates::Krates = kb.build_with_metadata(md, krates::NoneFilter).unwrap();
+
+    // time-macros is a proc-macro enabled by time's `macros` feature, and
+    // `time-macros?/formatting` and `time-macros?/parsing` are in time's
+    // `formatting` and `parsing` features
+    let km = krates.krates_by_name("time-macros").next().unwrap();
+    let units = krates.get_unit_features(km.kid).unwrap();
+    assert_eq!(units.target, None);
+    assert_eq!(units.host.as_ref(), krates.get_enabled_features(km.kid));
+    assert!(units.host.as_ref().unwrap().contains("formatting"));
+}
+
+/// Ensures the host features of proc-macros that are dev-dependencies, and of
+/// their dependencies, are resolved when building tests
+#[test]
+fn resolves_unit_dev_host_features() {
+    let contents = std::fs::read_to_string("tests/all-features.json").unwrap();
+    let mut md: serde_json::Value = serde_json::from_str(&contents).unwrap();
+
+    let a_id = "a 0.1.0 (path+file:///home/jake/code/krates/tests/ws/a)";
+    let macro_id =
+        "wasm-bindgen-macro 0.2.58 (registry+https://github.com/rust-lang/crates.io-index)";
+    let support_id =
+        "wasm-bindgen-macro-support 0.2.58 (registry+https://github.com/rust-lang/crates.io-index)";
+
+    // Make `a` depend on the wasm-bindgen-macro proc-macro when building
+    // tests, enabling a feature that nothing else enables
+    for pkg in md["packages"].as_array_mut().unwrap() {
+        if pkg["id"] == a_id {
+            pkg["dependencies"]
+                .as_array_mut()
+                .unwrap()
+                .push(serde_json::json!({
+                    "name": "wasm-bindgen-macro",
+                    "source": "registry+https://github.com/rust-lang/crates.io-index",
+                    "req": "^0.2",
+                    "kind": "dev",
+                    "rename": null,
+                    "optional": false,
+                    "uses_default_features": true,
+                    "features": ["strict-macro"],
+                    "target": null,
+                    "registry": null,
+                }));
+        }
+    }
+
+    for node in md["resolve"]["nodes"].as_array_mut().unwrap() {
+        if node["id"] == a_id {
+            node["deps"]
+                .as_array_mut()
+                .unwrap()
+                .push(serde_json::json!({
+                    "name": "wasm_bindgen_macro",
+                    "pkg": macro_id,
+                    "dep_kinds": [{ "kind": "dev", "target": null }],
+                }));
+        } else if node["id"] == macro_id || node["id"] == support_id {
+            node["features"]
+                .as_array_mut()
+                .unwrap()
+                .push("strict-macro".into());
+        }
+    }
+
+    let md: krates::cm::Metadata = serde_json::from_value(md).unwrap();
+    let mut kb = krates::Builder::new();
+    kb.unit_features(true);
+    let krates: krates::Krates = kb.build_with_metadata(md, krates::NoneFilter).unwrap();
+
+    let units = |name: &str| {
+        let km = krates.krates_by_name(name).next().unwrap();
+        krates.get_unit_features(km.kid).unwrap().clone()
+    };
+
+    for name in ["wasm-bindgen-macro", "wasm-bindgen-macro-support"] {
+        let units = units(name);
+        assert_eq!(units.target, None, "{name}");
+        assert_eq!(units.dev, None, "{name}");
+
+        let host = units.host.unwrap();
+        let dev_host = units.dev_host.unwrap();
+        assert!(!host.contains("strict-macro"), "{name}");
+        assert!(dev_host.contains("strict-macro"), "{name}");
+        assert!(dev_host.is_superset(&host), "{name}");
+    }
+}
+
+/// Ensures the features enabled on root crates can be changed without needing
+/// to run `cargo metadata` again
+#[test]
+fn resolves_root_features() {
+    let load = || -> krates::cm::Metadata {
+        let contents = std::fs::read_to_string("tests/all-features.json").unwrap();
+        serde_json::from_str(&contents).unwrap()
+    };
+
+    let c_kid = load()
+        .workspace_members
+        .into_iter()
+        .find(|wm| wm.repr.starts_with("c "))
+        .unwrap();
+
+    let build = |kid: &krates::cm::PackageId,
+                 only_c: bool,
+                 features: &[&str],
+                 default: bool|
+     -> Result<krates::Krates, krates::Error> {
+        let mut md = load();
+
+        // Pretend the metadata was gathered for `c` rather than the workspace
+        if only_c {
+            md.resolve.as_mut().unwrap().root = Some(c_kid.clone());
+        }
+
+        let mut kb = krates::Builder::new();
+        kb.root_features(kid.clone().into(), features.iter().copied(), default);
+        kb.build_with_metadata(md, krates::NoneFilter)
+    };
+
+    let features = |krates: &krates::Krates| -> Vec<String> {
+        krates
+            .get_enabled_features(&c_kid.clone().into())
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    };
+    let has_leftpad = |krates: &krates::Krates| krates.krates_by_name("leftpad").next().is_some();
+
+    // `b` depends on `c` with default features, so they are still enabled
+    let krates = build(&c_kid, false, &["lazy_static"], false).unwrap();
+    assert_eq!(features(&krates), ["default", "lazy_static", "leftpad"]);
+    assert!(has_leftpad(&krates));
+
+    // If `c` is the only root, nothing else enables its features
+    let krates = build(&c_kid, true, &[], false).unwrap();
+    assert!(features(&krates).is_empty());
+    assert!(!has_leftpad(&krates));
+
+    let krates = build(&c_kid, true, &["leftier-strings"], false).unwrap();
+    assert_eq!(features(&krates), ["leftier-strings", "leftpad"]);
+    assert!(has_leftpad(&krates));
+
+    let krates = build(&c_kid, true, &[], true).unwrap();
+    assert_eq!(features(&krates), ["default", "leftpad"]);
+
+    assert!(matches!(
+        build(&c_kid, true, &["not-a-feature"], true),
+        Err(krates::Error::UnknownRootFeature { feature, .. }) if feature == "not-a-feature"
+    ));
+
+    let leftpad = load()
+        .packages
+        .into_iter()
+        .find(|pkg| pkg.name == "leftpad")
+        .unwrap()
+        .id;
+    assert!(matches!(
+        build(&leftpad, true, &[], true),
+        Err(krates::Error::UnknownRoot(_))
+    ));
+}
+
 use krates::{Feature, ParsedFeature};
 use ktest::similar_asserts::assert_eq;
 
@@ -0,0 +1,148 @@
+use ktest::util::build_krates;
+
+/// Tests that the name and feature lookups find the same nodes as scanning
+/// the graph
+#[test]
+fn indexed_lookups() {
+    use krates::KrateDetails;
+
+    for fixture in ["all-features", "direct", "pid-opaque", "pid-stable"] {
+        let krates: krates::Krates =
+            build_krates(format!("{fixture}.json"), krates::Builder::new()).unwrap();
+
+        for krate in krates.krates() {
+            let scanned: Vec<_> = krates
+                .graph()
+                .node_indices()
+                .filter(|nid| {
+                    matches!(&krates.graph()[*nid], krates::Node::Krate { krate: k, .. } if k.name() == krate.name())
+                })
+                .collect();
+            let found: Vec<_> = krates
+                .krates_by_name(krate.name.as_str())
+                .map(|km| km.node_id)
+                .collect();
+            assert_eq!(found, scanned, "{fixture} {}", krate.name);
+
+            let req = krates::semver::VersionReq::parse(&format!("={}", krate.version)).unwrap();
+            assert!(
+                krates
+                    .search_matches(krate.name.as_str(), req)
+                    .all(|km| km.krate.version() == krate.version())
+            );
+        }
+
+        assert_eq!(krates.krates_by_name("not-a-crate").count(), 0);
+
+        for nid in krates.graph().node_indices() {
+            let krates::Node::Feature { krate_index, name } = &krates.graph()[nid] else {
+                continue;
+            };
+
+            let krates::Node::Krate { id: kid, .. } = &krates.graph()[*krate_index] else {
+                unreachable!();
+            };
+            let (found, _) = krates.get_node(kid, Some(name)).unwrap();
+
+            // A feature can have more than one node, in which case the first
+            // is always the one found
+            let first = krates
+                .graph()
+                .node_indices()
+                .find(|other| {
+                    matches!(&krates.graph()[*other], krates::Node::Feature { krate_index: ki, name: n } if ki == krate_index && n == name)
+                })
+                .unwrap();
+            assert_eq!(found, first);
+            assert!(krates.get_node(kid, Some("not-a-feature")).is_none());
+        }
+    }
+}
+
+/// Tests that crates are looked up by their package name, even when the name
+/// in the package id is different
+#[test]
+fn lookups_use_package_name() {
+    for fixture in ["pid-opaque", "pid-stable"] {
+        // Git ids only include the package name if it differs from the name
+        // of the repository, so make this objc-sys id parse as objc2 instead
+        let contents = std::fs::read_to_string(format!("tests/{fixture}.json"))
+            .unwrap()
+            .replace(
+                "git+https://github.com/madsmtm/objc2?rev=65de002#objc-sys@0.2.0-beta.2",
+                "git+https://github.com/madsmtm/objc2?rev=65de002#0.2.0-beta.2",
+            );
+        let md: krates::cm::Metadata = serde_json::from_str(&contents).unwrap();
+
+        let krates: krates::Krates = krates::Builder::new()
+            .build_with_metadata(md, krates::NoneFilter)
+            .unwrap();
+
+        let versions = |name: &str| {
+            krates
+                .krates_by_name(name)
+                .map(|km| {
+                    assert_eq!(km.krate.name, name);
+                    km.krate.version.to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let objc_sys = versions("objc-sys");
+        assert_eq!(objc_sys.len(), 2, "{fixture}");
+        assert!(objc_sys.iter().any(|vs| vs == "0.2.0-beta.2"), "{fixture}");
+
+        let git = krates
+            .krates()
+            .find(|krate| krate.name == "objc-sys" && krate.version.to_string() == "0.2.0-beta.2")
+            .unwrap();
+        assert_eq!(krates::Kid::from(git.id.clone()).name(), "objc2");
+
+        let objc2 = versions("objc2");
+        assert_eq!(objc2.len(), 3, "{fixture}");
+        assert!(objc2.iter().all(|vs| vs != "0.2.0-beta.2"), "{fixture}");
+    }
+}
+
+/// Tests that crates are looked up by the name of the node, even when the
+/// nodes are converted to a type with different names
+#[test]
+fn lookups_use_node_name() {
+    struct Prefixed {
+        name: String,
+        version: krates::semver::Version,
+    }
+
+    impl krates::KrateDetails for Prefixed {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn version(&self) -> &krates::semver::Version {
+            &self.version
+        }
+    }
+
+    let krates: krates::Krates = build_krates("all-features.json", krates::Builder::new()).unwrap();
+
+    // Looking up a crate before mapping doesn't affect the mapped graph
+    assert_eq!(krates.krates_by_name("c").count(), 1);
+
+    let mapped: krates::Krates<Prefixed> = krates.map(
+        |_kid, krate| Prefixed {
+            name: format!("my-{}", krate.name),
+            version: krate.version.clone(),
+        },
+        Clone::clone,
+    );
+
+    assert_eq!(mapped.krates_by_name("c").count(), 0);
+    let found: Vec<_> = mapped.krates_by_name("my-c").map(|km| km.node_id).collect();
+    assert_eq!(
+        found,
+        krates
+            .krates_by_name("c")
+            .map(|km| km.node_id)
+            .collect::<Vec<_>>()
+    );
+}
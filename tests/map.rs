@@ -0,0 +1,79 @@
+use ktest::util::build_krates;
+
+/// Tests that mapping a graph keeps its structure and lookups, and that
+/// `try_map` stops at the first error
+#[test]
+fn maps_graphs() {
+    use krates::petgraph::visit::EdgeRef;
+
+    let krates: krates::Krates = build_krates("all-features.json", krates::Builder::new()).unwrap();
+
+    let mapped: krates::Krates<(String, String), String> = krates.map(
+        |kid, krate| (kid.repr.clone(), krate.name.clone()),
+        |edge| edge.to_string(),
+    );
+
+    assert_eq!(mapped.len(), krates.len());
+    assert_eq!(mapped.graph().node_count(), krates.graph().node_count());
+    assert_eq!(
+        mapped.workspace_members().count(),
+        krates.workspace_members().count()
+    );
+
+    for krate in krates.krates() {
+        let kid: krates::Kid = krate.id.clone().into();
+        let nid = krates.nid_for_kid(&kid).unwrap();
+        assert_eq!(mapped.nid_for_kid(&kid), Some(nid));
+        assert_eq!(mapped[nid], (kid.repr.clone(), krate.name.clone()));
+        assert_eq!(
+            mapped.get_enabled_features(&kid),
+            krates.get_enabled_features(&kid)
+        );
+    }
+
+    for (expected, actual) in krates
+        .graph()
+        .edge_references()
+        .zip(mapped.graph().edge_references())
+    {
+        assert_eq!(expected.id(), actual.id());
+        assert_eq!(expected.source(), actual.source());
+        assert_eq!(expected.target(), actual.target());
+        assert_eq!(&expected.weight().to_string(), actual.weight());
+    }
+
+    let tried: krates::Krates<String, String> = krates
+        .try_map(
+            |kid, _krate| Ok::<_, String>(kid.repr.clone()),
+            |edge| Ok(edge.to_string()),
+        )
+        .unwrap();
+
+    for (nid, krate) in mapped.krates().enumerate() {
+        assert_eq!(tried[krates::NodeId::new(nid)], krate.0);
+    }
+
+    assert!(
+        tried
+            .graph()
+            .edge_references()
+            .zip(mapped.graph().edge_references())
+            .all(|(a, b)| a.source() == b.source()
+                && a.target() == b.target()
+                && a.weight() == b.weight())
+    );
+
+    let last = krates.krates().last().unwrap().name.clone();
+    let failed = krates.try_map(
+        |kid, _krate| {
+            if kid.name() == last {
+                Err(kid.clone())
+            } else {
+                Ok(())
+            }
+        },
+        |_edge| Ok(()),
+    );
+
+    assert_eq!(failed.err().unwrap().name(), last);
+}
@@ -336,3 +336,148 @@ fn ignores_unknown_fields() {
 fn windows_paths() {
     ktest::assert_dotgraph!(default "windows.json");
 }
+
+/// Ensures that metadata that doesn't match up with itself results in errors
+/// or diagnostics rather than panics
+#[test]
+fn handles_malformed_metadata() {
+    use krates::Diagnostic;
+    use std::sync::{Arc, Mutex};
+
+    let build = |replacements: &[(&str, &str)]| -> Result<Vec<Diagnostic>, krates::Error> {
+        let mut contents = std::fs::read_to_string("tests/all-features.json").unwrap();
+        for (from, to) in replacements {
+            assert!(contents.contains(from));
+            contents = contents.replace(from, to);
+        }
+
+        let md: krates::cm::Metadata = serde_json::from_str(&contents).unwrap();
+
+        let diags = Arc::new(Mutex::new(Vec::new()));
+        let d = diags.clone();
+
+        let mut kb = krates::Builder::new();
+        kb.on_diagnostic(move |diag| d.lock().unwrap().push(diag));
+        let _krates: krates::Krates = kb.build_with_metadata(md, krates::NoneFilter)?;
+        Ok(std::mem::take(&mut *diags.lock().unwrap()))
+    };
+
+    assert!(build(&[]).unwrap().is_empty());
+
+    let diags = build(&[
+        (
+            r#""leftier-strings":["leftpad"]"#,
+            r#""leftier-strings":["leftpad","not-a-feature"]"#,
+        ),
+        (
+            r#""rename":"nix-xy","optional":false,"uses_default_features":true,"features":[]"#,
+            r#""rename":"nix-xy","optional":false,"uses_default_features":true,"features":["bogus"]"#,
+        ),
+    ])
+    .unwrap();
+
+    let mut unknown: Vec<_> = diags
+        .iter()
+        .filter_map(|diag| match diag {
+            Diagnostic::UnknownFeature { krate, feature } => {
+                Some(format!("{}/{feature}", krate.name()))
+            }
+            _ => None,
+        })
+        .collect();
+    unknown.sort();
+    assert_eq!(unknown, ["c/not-a-feature", "nix/bogus"]);
+
+    // Change the id of a package that is referenced by the resolve graph
+    let err = build(&[(
+        r#""id":"leftpad 0.2.0 (registry+https://github.com/rust-lang/crates.io-index)","license""#,
+        r#""id":"leftpad 0.2.1 (registry+https://github.com/rust-lang/crates.io-index)","license""#,
+    )])
+    .unwrap_err();
+    assert!(
+        matches!(&err, krates::Error::MissingPackage(kid) if kid.repr.starts_with("leftpad 0.2.0")),
+        "{err}"
+    );
+}
+
+/// Ensures that a graph without feature nodes still contains the same crates,
+/// dependencies, and enabled features
+#[test]
+fn crates_only() {
+    use std::collections::BTreeSet;
+
+    for fixture in ["all-features.json", "all-features2.json", "direct.json"] {
+        let build = |crates_only: bool| -> krates::Krates {
+            let contents = std::fs::read_to_string(format!("tests/{fixture}")).unwrap();
+            let md: krates::cm::Metadata = serde_json::from_str(&contents).unwrap();
+
+            let mut kb = krates::Builder::new();
+            kb.crates_only(crates_only);
+            kb.build_with_metadata(md, krates::NoneFilter).unwrap()
+        };
+
+        let full = build(false);
+        let crates = build(true);
+
+        assert_eq!(crates.len(), full.len());
+        assert_eq!(crates.graph().node_count(), crates.len());
+
+        assert!(
+            crates
+                .graph()
+                .edge_weights()
+                .all(|edge| matches!(edge, krates::Edge::Dep { .. }))
+        );
+
+        // The crate level edges, ie. Dep and DepFeature edges from a crate
+        let deps = |krates: &krates::Krates| -> BTreeSet<(String, String, String)> {
+            use krates::petgraph::visit::EdgeRef;
+
+            let graph = krates.graph();
+            graph
+                .edge_references()
+                .filter_map(|edge| {
+                    let krates::Node::Krate { id: src, .. } = &graph[edge.source()] else {
+                        return None;
+                    };
+
+                    let target = match &graph[edge.target()] {
+                        krates::Node::Krate { .. } => edge.target(),
+                        krates::Node::Feature { krate_index, .. } => *krate_index,
+                    };
+
+                    let (krates::Edge::Dep {
+                        kind,
+                        cfg,
+                        declaration,
+                    }
+                    | krates::Edge::DepFeature {
+                        kind,
+                        cfg,
+                        declaration,
+                    }) = edge.weight()
+                    else {
+                        return None;
+                    };
+
+                    Some((
+                        src.repr.clone(),
+                        krates[target].id.repr.clone(),
+                        format!("{kind:?} {cfg:?} {}", declaration.index),
+                    ))
+                })
+                .collect()
+        };
+
+        assert_eq!(deps(&crates), deps(&full), "{fixture}");
+
+        for krate in full.krates() {
+            let kid = krate.id.clone().into();
+            assert_eq!(
+                crates.get_enabled_features(&kid),
+                full.get_enabled_features(&kid),
+                "{fixture} {kid}"
+            );
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use ktest::util::{build_krates, dev_cycle_krates};
+
+/// Tests that crates are ordered after their dependencies, breaking
+/// dev-dependency cycles
+#[test]
+fn build_order() {
+    for fixture in ["all-features", "direct", "pid-stable"] {
+        let krates: krates::Krates =
+            build_krates(format!("{fixture}.json"), krates::Builder::new()).unwrap();
+
+        let levels = krates.levels();
+        let mut level_of = vec![usize::MAX; krates.len()];
+        for (i, level) in levels.levels.iter().enumerate() {
+            assert!(!level.is_empty());
+            for nid in level {
+                assert_eq!(level_of[nid.index()], usize::MAX, "placed twice");
+                level_of[nid.index()] = i;
+            }
+        }
+        assert!(level_of.iter().all(|level| *level != usize::MAX));
+
+        for broken in &levels.broken {
+            assert!(broken.dev_only);
+        }
+
+        for (i, level) in levels.levels.iter().enumerate() {
+            for nid in level {
+                let deps: Vec<_> = krates
+                    .direct_dependencies(*nid)
+                    .into_iter()
+                    .map(|dd| dd.node_id)
+                    .filter(|dep| dep != nid)
+                    .filter(|dep| {
+                        !levels
+                            .broken
+                            .iter()
+                            .any(|b| b.dependent == *nid && b.dependency == *dep)
+                    })
+                    .collect();
+
+                // Every dependency is in an earlier level, and at least one is
+                // in the level right before, otherwise the crate would be earlier
+                assert!(deps.iter().all(|dep| level_of[dep.index()] < i));
+                assert_eq!(
+                    deps.iter().any(|dep| level_of[dep.index()] + 1 == i),
+                    i != 0
+                );
+            }
+        }
+
+        let topo = krates.topo_order();
+        assert_eq!(
+            topo.order,
+            levels.levels.into_iter().flatten().collect::<Vec<_>>()
+        );
+        assert!(topo.broken.is_empty());
+    }
+
+    let (krates, memchr, aho) = dev_cycle_krates();
+    assert!(
+        krates
+            .direct_dependencies(memchr)
+            .iter()
+            .any(|dd| dd.node_id == aho)
+    );
+
+    let topo = krates.topo_order();
+    assert_eq!(
+        topo.broken,
+        vec![krates::BrokenDependency {
+            dependent: memchr,
+            dependency: aho,
+            dev_only: true,
+        }]
+    );
+
+    let position = |nid: krates::NodeId| topo.order.iter().position(|o| *o == nid).unwrap();
+    assert!(position(memchr) < position(aho));
+    assert_eq!(topo.order.len(), krates.len());
+}
@@ -0,0 +1,111 @@
+//! The graph built with the `parallel` feature must be exactly the same as the
+//! one built sequentially, so these tests are run both with and without the
+//! feature against the same snapshots
+
+use krates::{Edge, Node};
+use ktest::util::{JustId, metadata};
+use std::fmt::Write;
+
+/// Writes out every node and edge in the graph, including the data that isn't
+/// part of the dot graphs used by the other tests
+fn dump(krates: &krates::Krates<JustId>) -> String {
+    let mut out = String::new();
+
+    for (index, node) in krates.graph().node_weights().enumerate() {
+        match node {
+            Node::Krate {
+                krate,
+                features,
+                unit_features,
+                dep_mapping,
+                depth,
+                ..
+            } => {
+                writeln!(out, "{index}: crate {krate} depth={depth}").unwrap();
+                writeln!(out, "  features={features:?}").unwrap();
+
+                if let Some(uf) = unit_features {
+                    writeln!(out, "  target={:?}", uf.target).unwrap();
+                    writeln!(out, "  host={:?}", uf.host).unwrap();
+                    writeln!(out, "  dev={:?}", uf.dev).unwrap();
+                }
+
+                let mapping: Vec<_> = dep_mapping
+                    .iter()
+                    .map(|nid| nid.map(|nid| nid.index()))
+                    .collect();
+                writeln!(out, "  dep_mapping={mapping:?}").unwrap();
+            }
+            Node::Feature { krate_index, name } => {
+                writeln!(out, "{index}: feature {}/{name}", krate_index.index()).unwrap();
+            }
+        }
+    }
+
+    for edge in krates.graph().raw_edges() {
+        let (source, target) = (edge.source().index(), edge.target().index());
+
+        match &edge.weight {
+            Edge::Dep { declaration, .. } => {
+                writeln!(
+                    out,
+                    "{source} -> {target} dep{} #{}",
+                    edge.weight, declaration.index
+                )
+            }
+            Edge::DepFeature { declaration, .. } => writeln!(
+                out,
+                "{source} -> {target} dep-feature{} #{}",
+                edge.weight, declaration.index
+            ),
+            Edge::Feature => writeln!(out, "{source} -> {target} feature"),
+        }
+        .unwrap();
+    }
+
+    out
+}
+
+fn builder() -> krates::Builder {
+    let mut kb = krates::Builder::new();
+    kb.include_targets([
+        ("x86_64-unknown-linux-gnu", vec![]),
+        ("x86_64-pc-windows-msvc", vec![]),
+    ])
+    .unit_features(true);
+    kb
+}
+
+#[test]
+fn matches_sequential_build() {
+    let md = metadata("all-features.json").unwrap();
+    let krates: krates::Krates<JustId> = builder()
+        .build_with_metadata(md, krates::NoneFilter)
+        .unwrap();
+
+    ktest::assert_snapshot!(dump(&krates));
+}
+
+/// Ensures that packages that aren't part of the resolve graph don't throw off
+/// the lookup of the package for each crate
+#[test]
+fn ignores_unresolved_packages() {
+    let md = metadata("all-features.json").unwrap();
+    let expected: krates::Krates<JustId> = builder()
+        .build_with_metadata(md.clone(), krates::NoneFilter)
+        .unwrap();
+
+    let mut md = md;
+    let mut stray = md.packages[0].clone();
+    stray.id.repr =
+        "aaa-stray 0.1.0 (registry+https://github.com/rust-lang/crates.io-index)".into();
+    md.packages.push(stray);
+
+    let mut filtered = Vec::new();
+    let actual: krates::Krates<JustId> = builder()
+        .build_with_metadata(md, |pkg: krates::cm::Package| filtered.push(pkg.id.repr))
+        .unwrap();
+
+    assert!(filtered.iter().any(|id| id.starts_with("aaa-stray ")));
+    ktest::similar_asserts::assert_eq!(dump(&expected), dump(&actual));
+}
@@ -0,0 +1,120 @@
+use ktest::util::build_krates;
+
+/// Tests that every path leads from a workspace member to the crate, one
+/// dependency at a time
+#[test]
+fn dependency_paths() {
+    let krates: krates::Krates = build_krates("all-features.json", krates::Builder::new()).unwrap();
+
+    let graph = krates.graph();
+    let owner = |nid: krates::NodeId| match &graph[nid] {
+        krates::Node::Krate { .. } => nid,
+        krates::Node::Feature { krate_index, .. } => *krate_index,
+    };
+
+    let check = |target: krates::NodeId, path: &krates::DependencyPath<'_, krates::Edge>| {
+        let root = &graph[path.root];
+        assert!(krates.workspace_members().any(|wm| std::ptr::eq(wm, root)));
+
+        let mut current = path.root;
+        let mut visited = vec![path.root];
+        for hop in &path.hops {
+            assert_eq!(hop.dependent, current);
+            let (source, target) = graph.edge_endpoints(hop.edge_id).unwrap();
+            assert_eq!(owner(source), hop.dependent);
+            assert!(std::ptr::eq(hop.edge, &graph[hop.edge_id]));
+            assert_eq!(owner(target), hop.dependency);
+            assert!(
+                hop.features
+                    .iter()
+                    .all(|f| owner(*f) == hop.dependency && *f != hop.dependency)
+            );
+            assert!(!visited.contains(&hop.dependency));
+            visited.push(hop.dependency);
+            current = hop.dependency;
+        }
+
+        assert_eq!(current, target);
+    };
+
+    for nid in (0..krates.len()).map(krates::NodeId::new) {
+        let shortest = krates.shortest_path_to(nid).unwrap();
+        check(nid, &shortest);
+
+        let paths = krates.paths_to(nid, 20);
+        assert!(!paths.is_empty() && paths.len() <= 20);
+        for path in &paths {
+            check(nid, path);
+            assert!(path.hops.len() >= shortest.hops.len());
+        }
+    }
+
+    let find = |name: &str| {
+        krates
+            .krates_by_name(name)
+            .next()
+            .map(|km| km.node_id)
+            .unwrap()
+    };
+    let names = |path: &krates::DependencyPath<'_, krates::Edge>| {
+        std::iter::once(krates[path.root].name.as_str())
+            .chain(
+                path.hops
+                    .iter()
+                    .map(|hop| krates[hop.dependency].name.as_str()),
+            )
+            .collect::<Vec<_>>()
+    };
+    let features = |hop: &krates::PathHop<'_, krates::Edge>| {
+        hop.features
+            .iter()
+            .map(|nid| match &graph[*nid] {
+                krates::Node::Feature { name, .. } => name.as_str(),
+                krates::Node::Krate { .. } => unreachable!(),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let aho = krates.shortest_path_to(find("aho-corasick")).unwrap();
+    assert_eq!(
+        names(&aho),
+        [
+            "c",
+            "coreaudio-rs",
+            "coreaudio-sys",
+            "bindgen",
+            "regex",
+            "aho-corasick"
+        ]
+    );
+    assert!(matches!(
+        aho.hops[2].edge,
+        krates::Edge::Dep {
+            kind: krates::DepKind::Build,
+            ..
+        }
+    ));
+    assert!(matches!(
+        aho.hops[0].edge,
+        krates::Edge::DepFeature { cfg: Some(cfg), .. } if cfg == "x86_64-apple-darwin"
+    ));
+    // regex only depends on aho-corasick via one of its features
+    assert!(matches!(aho.hops[4].edge, krates::Edge::Feature));
+    assert_eq!(
+        features(&aho.hops[3]),
+        ["default", "perf", "perf-literal", "aho-corasick"]
+    );
+
+    let root = find("c");
+    let own = krates.shortest_path_to(root).unwrap();
+    assert_eq!(own.root, root);
+    assert!(own.hops.is_empty());
+
+    assert!(krates.paths_to(find("proc-macro2"), 1000).len() > 20);
+    assert!(krates.paths_to(find("proc-macro2"), 0).is_empty());
+    assert!(
+        krates
+            .shortest_path_to(krates::NodeId::new(krates.len()))
+            .is_none()
+    );
+}
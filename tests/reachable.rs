@@ -0,0 +1,65 @@
+use ktest::util::build_krates;
+
+/// Tests that reachability only walks the edges that match the predicate
+#[test]
+fn reachable_crates() {
+    let krates: krates::Krates = build_krates("all-features.json", krates::Builder::new()).unwrap();
+
+    let members: Vec<_> = krates
+        .workspace_members()
+        .filter_map(|node| match node {
+            krates::Node::Krate { id, .. } => krates.nid_for_kid(id),
+            krates::Node::Feature { .. } => None,
+        })
+        .collect();
+
+    let all = krates.reachable(members.iter().copied(), |_, _| true);
+    assert_eq!(all.len(), krates.len());
+
+    let mut roots = members.clone();
+    roots.sort();
+    assert_eq!(
+        krates.reachable(members.iter().copied(), |_, _| false),
+        roots
+    );
+
+    let kind_of = |edge: &krates::Edge| match edge {
+        krates::Edge::Dep { kind, .. } | krates::Edge::DepFeature { kind, .. } => Some(*kind),
+        krates::Edge::Feature => None,
+    };
+
+    for filter in [
+        krates::DepKind::Normal,
+        krates::DepKind::Build,
+        krates::DepKind::Dev,
+    ] {
+        let reachable: Vec<_> = krates
+            .reachable(members.iter().copied(), |_, edge| {
+                kind_of(edge) != Some(filter)
+            })
+            .into_iter()
+            .map(|nid| krates[nid].id.repr.clone())
+            .collect();
+        let filtered: Vec<_> = krates
+            .krates_filtered(filter)
+            .into_iter()
+            .map(|pkg| pkg.id.repr.clone())
+            .collect();
+        assert_eq!(reachable, filtered);
+    }
+
+    // Ignoring build dependencies only for crates outside of the workspace
+    // keeps the build dependencies of the workspace crates
+    let mut sources = std::collections::BTreeSet::new();
+    let non_ws_build = krates.reachable(members.iter().copied(), |source, edge| {
+        sources.insert(source);
+        kind_of(edge) != Some(krates::DepKind::Build) || members.contains(&source)
+    });
+    assert!(sources.iter().all(|nid| nid.index() < krates.len()));
+
+    let no_build = krates.reachable(members.iter().copied(), |_, edge| {
+        kind_of(edge) != Some(krates::DepKind::Build)
+    });
+    assert!(no_build.iter().all(|nid| non_ws_build.contains(nid)));
+    assert!(non_ws_build.iter().all(|nid| all.contains(nid)));
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "serialize")]
+
+use ktest::util::build_krates;
+
+/// Tests that a serialized graph round trips, and that invalid graphs are
+/// rejected when deserializing
+#[test]
+fn serializes_graphs() {
+    use krates::petgraph::visit::EdgeRef;
+
+    let krates: krates::Krates = build_krates("all-features.json", krates::Builder::new()).unwrap();
+
+    let serialized = serde_json::to_value(&krates).unwrap();
+    let loaded: krates::Krates = serde_json::from_value(serialized.clone()).unwrap();
+
+    assert_eq!(serde_json::to_value(&loaded).unwrap(), serialized);
+    assert_eq!(loaded.len(), krates.len());
+    assert_eq!(loaded.graph().node_count(), krates.graph().node_count());
+    assert_eq!(
+        loaded.workspace_members().count(),
+        krates.workspace_members().count()
+    );
+
+    for krate in krates.krates() {
+        let kid: krates::Kid = krate.id.clone().into();
+        assert_eq!(loaded.nid_for_kid(&kid), krates.nid_for_kid(&kid));
+        assert_eq!(
+            loaded.get_enabled_features(&kid),
+            krates.get_enabled_features(&kid)
+        );
+
+        let by_name = |krates: &krates::Krates| {
+            krates
+                .krates_by_name(krate.name.as_str())
+                .map(|km| km.node_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(by_name(&loaded), by_name(&krates));
+    }
+
+    assert!(
+        loaded
+            .graph()
+            .edge_references()
+            .zip(krates.graph().edge_references())
+            .all(|(a, b)| a.source() == b.source()
+                && a.target() == b.target()
+                && format!("{:?}", a.weight()) == format!("{:?}", b.weight()))
+    );
+
+    let load = |value: serde_json::Value| {
+        serde_json::from_value::<krates::Krates>(value)
+            .err()
+            .unwrap()
+            .to_string()
+    };
+
+    let mut unsupported = serialized.clone();
+    unsupported["version"] = 2.into();
+    assert!(load(unsupported).contains("unsupported format version 2"));
+
+    let mut unsorted = serialized.clone();
+    unsorted["crates"].as_array_mut().unwrap().swap(0, 1);
+    assert!(load(unsorted).contains("crates are not sorted"));
+
+    let mut bad_feature = serialized.clone();
+    bad_feature["features"][0][0] = krates.len().into();
+    assert!(load(bad_feature).contains("which is not a crate"));
+
+    let mut bad_edge = serialized.clone();
+    bad_edge["edges"][0][1] = krates.graph().node_count().into();
+    assert!(load(bad_edge).contains("refers to a node that does not exist"));
+
+    let mut bad_member = serialized.clone();
+    let member = bad_member["workspace_members"]
+        .as_array_mut()
+        .unwrap()
+        .last_mut()
+        .unwrap();
+    let repr = member[0].as_str().unwrap();
+    member[0] = format!("z{}", &repr[1..]).into();
+    let unknown = member.clone();
+    assert!(load(bad_member).contains("is not a crate in the graph"));
+
+    let mut bad_root = serialized.clone();
+    bad_root["no_default_roots"] = serde_json::json!([unknown]);
+    assert!(load(bad_root).contains("root without default features"));
+
+    let mut bad_id = serialized;
+    bad_id["crates"][0]["id"][1][0] = serde_json::json!([0, 100_000]);
+    assert!(load(bad_id).contains("invalid component range"));
+}
@@ -0,0 +1,181 @@
+use ktest::util::build_krates;
+
+/// Tests that subgraphs only contain what is reachable from their roots
+#[test]
+fn subgraphs() {
+    let krates: krates::Krates = build_krates("all-features.json", krates::Builder::new()).unwrap();
+
+    let members: Vec<_> = krates
+        .workspace_members()
+        .filter_map(|node| match node {
+            krates::Node::Krate { id, .. } => krates.nid_for_kid(id),
+            krates::Node::Feature { .. } => None,
+        })
+        .collect();
+
+    fn with_features(krates: &krates::Krates) -> Vec<(&krates::Kid, &krates::EnabledFeatures)> {
+        krates
+            .graph()
+            .node_weights()
+            .filter_map(|node| match node {
+                krates::Node::Krate { id, features, .. } => Some((id, features)),
+                krates::Node::Feature { .. } => None,
+            })
+            .collect()
+    }
+
+    // Using every workspace member as a root gives the same graph
+    let full = krates
+        .subgraph(members.iter().copied(), &krates::SubgraphOptions::new())
+        .unwrap();
+    assert_eq!(full.len(), krates.len());
+    assert_eq!(full.graph().node_count(), krates.graph().node_count());
+    assert_eq!(full.graph().edge_count(), krates.graph().edge_count());
+    assert_eq!(with_features(&full), with_features(&krates));
+
+    // Ignoring dev dependencies gives the same crates as filtering them
+    let no_dev = krates
+        .subgraph(
+            members.iter().copied(),
+            krates::SubgraphOptions::new().ignore_kind(krates::DepKind::Dev, krates::Scope::All),
+        )
+        .unwrap();
+    let mut filtered: Vec<_> = krates
+        .krates_filtered(krates::DepKind::Dev)
+        .into_iter()
+        .map(|pkg| pkg.id.repr.clone())
+        .collect();
+    filtered.sort();
+    assert_eq!(
+        no_dev
+            .krates()
+            .map(|pkg| pkg.id.repr.clone())
+            .collect::<Vec<_>>(),
+        filtered
+    );
+
+    // A single root only has its own dependencies, with features that are a
+    // subset of the ones in the full graph
+    let mut smaller = false;
+    for root in &members {
+        let sub = krates
+            .subgraph([*root], &krates::SubgraphOptions::new())
+            .unwrap();
+        assert!(sub.len() <= krates.len());
+        smaller |= sub.len() < krates.len();
+
+        let root_kid = &krates[*root].id;
+        let sub_members: Vec<_> = sub.workspace_members().collect();
+        assert_eq!(sub_members.len(), 1);
+        assert!(
+            matches!(sub_members[0], krates::Node::Krate { id, depth: 0, .. } if id.repr == root_kid.repr)
+        );
+
+        for (id, features) in with_features(&sub) {
+            let nid = sub.nid_for_kid(id).unwrap();
+            assert_eq!(sub[nid].id.repr, id.repr);
+
+            let original = krates.get_enabled_features(id).unwrap();
+            assert!(features.is_subset(original), "{id}");
+
+            let krates::Node::Krate { depth, .. } = &sub.graph()[nid] else {
+                unreachable!();
+            };
+            assert_eq!(*depth == 0, id.repr == root_kid.repr);
+        }
+
+        for edge in sub.graph().edge_indices() {
+            let (source, target) = sub.graph().edge_endpoints(edge).unwrap();
+            assert!(source.index() < sub.graph().node_count());
+            assert!(target.index() < sub.graph().node_count());
+        }
+    }
+
+    assert!(smaller);
+
+    assert!(
+        krates
+            .subgraph(std::iter::empty(), &krates::SubgraphOptions::new())
+            .is_err()
+    );
+}
+
+/// Tests that optional dependencies, and the features of every crate, are only
+/// enabled if a crate in the subgraph enables them
+#[test]
+fn resolves_subgraph_features() {
+    let krates: krates::Krates = build_krates("all-features.json", krates::Builder::new()).unwrap();
+
+    let find = |krates: &krates::Krates, name: &str| {
+        krates
+            .krates_by_name(name)
+            .next()
+            .map(|km| (km.node_id, km.kid.clone()))
+    };
+    let set = |feats: &[&str]| -> krates::EnabledFeatures {
+        feats.iter().map(|s| (*s).to_owned()).collect()
+    };
+
+    // `c` only enables its optional dependency on `lazy_static` because its
+    // own features are enabled as a workspace member, not by `b`
+    let (b, _) = find(&krates, "b").unwrap();
+    let sub = krates
+        .subgraph([b], &krates::SubgraphOptions::new())
+        .unwrap();
+
+    let (c, c_kid) = find(&sub, "c").unwrap();
+    assert!(
+        !sub.direct_dependencies(c)
+            .iter()
+            .any(|dd| dd.krate.name == "lazy_static")
+    );
+    assert_eq!(
+        sub.get_enabled_features(&c_kid).unwrap(),
+        &set(&["default", "leftpad"])
+    );
+    assert!(!sub.graph().node_weights().any(|node| matches!(
+        node,
+        krates::Node::Feature { krate_index, name } if *krate_index == c && name == "lazy_static"
+    )));
+
+    // The features for each unit are resolved again as well, and match the
+    // original graph when every workspace member is a root
+    for fixture in ["all-features.json", "direct.json", "pid-stable.json"] {
+        let mut kb = krates::Builder::new();
+        kb.unit_features(true);
+        let krates: krates::Krates = build_krates(fixture, kb).unwrap();
+
+        let members: Vec<_> = krates
+            .workspace_members()
+            .filter_map(|node| match node {
+                krates::Node::Krate { id, .. } => krates.nid_for_kid(id),
+                krates::Node::Feature { .. } => None,
+            })
+            .collect();
+
+        let full = krates
+            .subgraph(members, &krates::SubgraphOptions::new())
+            .unwrap();
+        for node in krates.graph().node_weights() {
+            if let krates::Node::Krate { id, .. } = node {
+                assert_eq!(
+                    full.get_unit_features(id),
+                    krates.get_unit_features(id),
+                    "{fixture} {id}"
+                );
+            }
+        }
+
+        if fixture == "all-features.json" {
+            let (b, _) = find(&krates, "b").unwrap();
+            let sub = krates
+                .subgraph([b], &krates::SubgraphOptions::new())
+                .unwrap();
+            let (_, c_kid) = find(&sub, "c").unwrap();
+            assert_eq!(
+                sub.get_unit_features(&c_kid).unwrap().target,
+                Some(set(&["default", "leftpad"]))
+            );
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use ktest::util::build_krates;
+
+/// Tests that the reverse tree walks every transitive dependent
+#[test]
+fn reverse_tree() {
+    use std::fmt::Write;
+
+    let krates: krates::Krates = build_krates("all-features.json", krates::Builder::new()).unwrap();
+
+    let memchr = krates.krates_by_name("memchr").next().unwrap().node_id;
+
+    let mut transitive = std::collections::BTreeSet::new();
+    let mut stack = vec![memchr];
+    while let Some(nid) = stack.pop() {
+        for dd in krates.direct_dependents(nid) {
+            if dd.node_id != memchr && transitive.insert(dd.node_id) {
+                stack.push(dd.node_id);
+            }
+        }
+    }
+
+    let walked: Vec<_> = krates.reverse_tree(memchr).collect();
+    let deduped: Vec<_> = krates.reverse_tree(memchr).dedup(true).collect();
+
+    assert!(walked.len() > deduped.len());
+    for tree in [&walked, &deduped] {
+        assert_eq!(
+            tree.iter()
+                .map(|rd| rd.node_id)
+                .collect::<std::collections::BTreeSet<_>>(),
+            transitive
+        );
+
+        // Every dependent's parent is either the root or the closest preceding
+        // dependent one level up
+        for (i, rd) in tree.iter().enumerate() {
+            let parent = tree[..i]
+                .iter()
+                .rev()
+                .find(|prev| prev.depth < rd.depth)
+                .map_or(memchr, |prev| prev.node_id);
+            assert_eq!(parent, rd.dependency);
+            assert_eq!(
+                krates.graph().edge_endpoints(rd.edge_id).unwrap().0,
+                rd.node_id
+            );
+        }
+    }
+
+    // Each crate is only expanded once when deduplicating
+    assert_eq!(
+        deduped.iter().filter(|rd| !rd.duplicate).count(),
+        transitive.len()
+    );
+
+    let direct: std::collections::BTreeSet<_> = krates
+        .direct_dependents(memchr)
+        .into_iter()
+        .map(|dd| dd.node_id)
+        .collect();
+    assert_eq!(
+        krates
+            .reverse_tree(memchr)
+            .max_depth(1)
+            .map(|rd| rd.node_id)
+            .collect::<std::collections::BTreeSet<_>>(),
+        direct
+    );
+
+    let mut tree = String::new();
+    for rd in krates.reverse_tree(memchr).dedup(true).edge_filter(|edge| {
+        !matches!(
+            edge,
+            krates::Edge::Dep {
+                kind: krates::DepKind::Build,
+                ..
+            } | krates::Edge::DepFeature {
+                kind: krates::DepKind::Build,
+                ..
+            }
+        )
+    }) {
+        writeln!(
+            tree,
+            "{:width$}{}{}",
+            "",
+            rd.krate.name,
+            if rd.duplicate { " (*)" } else { "" },
+            width = rd.depth * 2
+        )
+        .unwrap();
+    }
+
+    ktest::assert_snapshot!(tree);
+}
+
+/// Tests that the max depth limits what is yielded, not just what is walked,
+/// and that deduplication doesn't lose dependents of crates first reached at
+/// the max depth
+#[test]
+fn reverse_tree_max_depth() {
+    let krates: krates::Krates = build_krates("all-features.json", krates::Builder::new()).unwrap();
+
+    let memchr = krates.krates_by_name("memchr").next().unwrap().node_id;
+
+    assert_eq!(krates.reverse_tree(memchr).max_depth(0).count(), 0);
+
+    for max_depth in 1..6 {
+        let walked: std::collections::BTreeSet<_> = krates
+            .reverse_tree(memchr)
+            .max_depth(max_depth)
+            .map(|rd| {
+                assert!(rd.depth <= max_depth);
+                rd.node_id
+            })
+            .collect();
+        let deduped: std::collections::BTreeSet<_> = krates
+            .reverse_tree(memchr)
+            .max_depth(max_depth)
+            .dedup(true)
+            .map(|rd| rd.node_id)
+            .collect();
+
+        assert_eq!(walked, deduped, "max depth {max_depth}");
+    }
+}
@@ -42,3 +42,76 @@ fn workspace_with_root_exclude() {
 
     ktest::assert_dotgraph!("all-features2.json", kb);
 }
+
+#[test]
+fn includes_specs() {
+    let by_path = {
+        let mut kb = krates::Builder::new();
+        kb.include_workspace_crates([
+            "/home/jake/code/krates/tests/ws2/b",
+            "/home/jake/code/krates/tests/ws2/c/Cargo.toml",
+        ]);
+        ktest::util::build("all-features2.json", kb).unwrap()
+    };
+
+    let mut kb = krates::Builder::new();
+    kb.include_workspace_specs(["b", "c@0.1.0"].iter().map(|spec| spec.parse().unwrap()));
+    let by_spec = ktest::util::build("all-features2.json", kb).unwrap();
+
+    assert_eq!(by_path.dotgraph(), by_spec.dotgraph());
+
+    // Specs and paths can be combined
+    let mut kb = krates::Builder::new();
+    kb.include_workspace_crates(["/home/jake/code/krates/tests/ws2/b"]);
+    kb.include_workspace_specs(std::iter::once("c".parse().unwrap()));
+    let mixed = ktest::util::build("all-features2.json", kb).unwrap();
+
+    assert_eq!(by_path.dotgraph(), mixed.dotgraph());
+}
+
+#[test]
+fn default_members() {
+    let contents = std::fs::read_to_string("tests/all-features2.json").unwrap();
+    let md: krates::cm::Metadata = serde_json::from_str(&contents).unwrap();
+
+    let build = |md: krates::cm::Metadata| -> ktest::util::Graph {
+        let mut kb = krates::Builder::new();
+        kb.default_members(true);
+        kb.build_with_metadata(md, krates::NoneFilter).unwrap()
+    };
+    let dot =
+        |krates: &ktest::util::Graph| krates::petgraph::dot::Dot::new(krates.graph()).to_string();
+
+    // Older cargo versions don't output the default members, so every member
+    // is used, even though the resolve graph has a root
+    assert!(md.workspace_default_members.0.is_none());
+    let workspace: ktest::util::Graph = {
+        let mut kb = krates::Builder::new();
+        kb.workspace(true);
+        kb.build_with_metadata(md.clone(), krates::NoneFilter)
+            .unwrap()
+    };
+    assert_eq!(dot(&workspace), dot(&build(md.clone())));
+
+    let mut md = md;
+    md.workspace_default_members.0 = Some(
+        md.workspace_members
+            .iter()
+            .filter(|id| id.repr.starts_with("b ") || id.repr.starts_with("c "))
+            .cloned()
+            .collect(),
+    );
+
+    let by_path: ktest::util::Graph = {
+        let mut kb = krates::Builder::new();
+        kb.include_workspace_crates([
+            "/home/jake/code/krates/tests/ws2/b",
+            "/home/jake/code/krates/tests/ws2/c/Cargo.toml",
+        ]);
+        kb.build_with_metadata(md.clone(), krates::NoneFilter)
+            .unwrap()
+    };
+
+    assert_ne!(dot(&workspace), dot(&by_path));
+    assert_eq!(dot(&by_path), dot(&build(md)));
+}